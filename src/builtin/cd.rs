@@ -14,14 +14,6 @@ pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
     let home = home::home_dir().unwrap();
     let mut new_cwd = String::new();
     let mut print_new_pwd = false;
-    // expand ~ into $HOME
-    for token in cmd_info.tokens.iter_mut() {
-        if let Some(x) = token.1.chars().nth(0) {
-            if x == '~' && token.0 != "\'" {
-                token.1 = home.as_path().display().to_string();
-            }
-        }
-    }
     // cd old new
     if argc >= 3 {
         new_cwd = sh.current_dir.clone();
@@ -42,7 +34,7 @@ pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
                         Ok(md) => {
                             if !md.is_dir() {
                                 eprint!("cd: not a directory: {}", cmd_info.tokens[1].1);
-                                return -1;
+                                return 1;
                             }
                         },
                         Err(_) => check_cdpath = true,
@@ -63,7 +55,7 @@ pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
     }
     if new_cwd.len() == 0 {
         eprint!("cd: no such file or directory: {}", cmd_info.tokens[1].1);
-        return -1;
+        return 1;
     }
     // call chdir after path finalized
     let c_path = CString::new(new_cwd).unwrap();
@@ -79,10 +71,10 @@ pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
                 pwd_short = pwd_short.replacen(&home.as_path().display().to_string() ,"~", 1);
                 println!("{}", pwd_short);
             }
-            return 0;
+            0
         } else {
             perror(c_cd.as_ptr());
-            return -1;
+            1
         }
     }
 }
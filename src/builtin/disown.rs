@@ -0,0 +1,49 @@
+use crate::{types::CmdInfo, mumsh::Mumsh};
+
+/// disown [-h] [-a] [%job ...]
+/// removes jobs from the job table, the current one by default, all of them with -a
+/// -h keeps them in the table, it is only about SIGHUP and mumsh sends none to her jobs
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let mut keep = false;
+    let mut all = false;
+    let mut specs = Vec::new();
+    for token in cmd_info.tokens.iter().skip(1) {
+        match token.1.as_str() {
+            "-h" => keep = true,
+            "-a" => all = true,
+            x if x.starts_with('-') => {
+                eprintln!("disown: {}: invalid option", x);
+                eprintln!("disown: usage: disown [-h] [-a] [%job ...]");
+                return 2;
+            },
+            x => specs.push(x.to_string()),
+        };
+    }
+    let mut status = 0;
+    let mut pgids = Vec::new();
+    if all {
+        pgids = sh.jobs.keys().copied().collect();
+    } else if specs.is_empty() {
+        specs.push(String::from("%+"));
+    }
+    for spec in specs.iter() {
+        match sh.find_job(spec) {
+            Ok(x) => pgids.push(x),
+            Err(e) => {
+                eprintln!("disown: {}", e);
+                status = 1;
+            }
+        };
+    }
+    if keep {
+        return status;
+    }
+    for pgid in pgids {
+        // they are still children of mumsh, to be reaped without a notice
+        if let Some(job) = sh.remove_job(pgid) {
+            sh.disowned.extend(job.pids);
+            sh.disowned.extend(job.helpers);
+        }
+    }
+    status
+}
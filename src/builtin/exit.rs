@@ -0,0 +1,28 @@
+use std::io::{self, Write};
+use std::process;
+
+use crate::{types::CmdInfo, mumsh::Mumsh};
+
+/// exit [n]
+/// exits mumsh with status n, or the status of the last command if not given
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let mut status = sh.last_status;
+    if let Some(x) = cmd_info.tokens.get(1) {
+        match x.1.parse::<i32>() {
+            Ok(n) => status = n & 0xff,
+            Err(_) => {
+                eprintln!("exit: numeric argument required: {}", x.1);
+                status = 2;
+            }
+        };
+    }
+    if cmd_info.tokens.len() > 2 {
+        eprintln!("exit: too many arguments");
+        return 1;
+    }
+    if sh.is_interactive {
+        println!("bye~");
+    }
+    io::stdout().flush().ok();
+    process::exit(status);
+}
@@ -0,0 +1,30 @@
+use std::env;
+
+use crate::{types::CmdInfo, mumsh::Mumsh, expand::param};
+
+/// export [name[=value] ...]
+/// moves shell variables into the environment, prints the environment without arguments
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let mut status = 0;
+    if cmd_info.tokens.len() == 1 || (cmd_info.tokens.len() == 2 && cmd_info.tokens[1].1 == "-p") {
+        let mut vars: Vec<(String, String)> = env::vars().collect();
+        vars.sort();
+        for (name, value) in vars {
+            println!("export {}=\"{}\"", name, value.replace('\\', "\\\\").replace('\"', "\\\""));
+        }
+        return 0;
+    }
+    for token in cmd_info.tokens.iter().skip(1) {
+        let (name, value) = match token.1.split_once('=') {
+            Some((x, y)) => (x, Some(y)),
+            None => (token.1.as_str(), None),
+        };
+        if !param::is_name(name) {
+            eprintln!("export: not a valid identifier: {}", name);
+            status = 1;
+            continue;
+        }
+        sh.export_var(name, value);
+    }
+    status
+}
@@ -0,0 +1,90 @@
+use nix::sys::signal::Signal;
+use termios::{tcsetattr, TCSADRAIN};
+
+use crate::{types::{CmdInfo, JobStatus}, mumsh::Mumsh};
+
+/// fg [%job], bg [%job ...]
+/// fg brings a job to the foreground and waits for it, bg lets stopped jobs go on in background
+/// the current job by default
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let name = cmd_info.tokens[0].1.clone();
+    if !sh.is_interactive {
+        eprintln!("{}: no job control", name);
+        return 1;
+    }
+    let mut specs: Vec<String> = cmd_info.tokens.iter().skip(1).map(|x| x.1.clone()).collect();
+    if specs.is_empty() {
+        specs.push(String::from("%+"));
+    }
+    if name == "fg" {
+        return match sh.find_job(&specs[0]) {
+            Ok(x) if sh.jobs[&x].is_done() => {
+                eprintln!("fg: job has terminated");
+                1
+            },
+            Ok(x) => foreground(x, sh),
+            Err(e) => {
+                eprintln!("fg: {}", e);
+                1
+            }
+        };
+    }
+    let mut status = 0;
+    for spec in specs.iter() {
+        let pgid = match sh.find_job(spec) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("bg: {}", e);
+                status = 1;
+                continue;
+            }
+        };
+        if sh.jobs[&pgid].is_done() {
+            eprintln!("bg: job has terminated");
+            status = 1;
+            continue;
+        }
+        if !sh.jobs[&pgid].is_stopped() {
+            eprintln!("bg: job {} already in background", sh.jobs[&pgid].id);
+            continue;
+        }
+        if let Err(e) = continue_job(pgid, JobStatus::Continued, sh) {
+            eprintln!("bg: {}: {}", spec, e.desc());
+            status = 1;
+            continue;
+        }
+        println!("{}", sh.job_line(pgid, false, false));
+    }
+    status
+}
+
+/// give the terminal to a job, continue it and wait until it is done or stopped again
+fn foreground(pgid: i32, sh: &mut Mumsh) -> i32 {
+    println!("{}", sh.jobs[&pgid].line);
+    sh.set_current_job(pgid);
+    sh.set_foreground_pg(pgid);
+    // the modes it had when it was suspended, eg: an editor in raw mode
+    if let Some(x) = sh.jobs[&pgid].tty_modes.as_ref() {
+        tcsetattr(sh.tty_fd, TCSADRAIN, x).ok();
+    }
+    let status = match continue_job(pgid, JobStatus::Running, sh) {
+        Ok(_) => sh.wait_job(pgid),
+        Err(e) => {
+            eprintln!("fg: {}", e.desc());
+            1
+        }
+    };
+    if sh.jobs.get(&pgid).map(|x| x.is_stopped()) == Some(true) {
+        sh.suspend_job(pgid);
+    } else {
+        sh.reclaim_terminal();
+    }
+    status
+}
+
+/// status is continued for bg, which reports it, its SIGCHLD then makes it running
+fn continue_job(pgid: i32, status: JobStatus, sh: &mut Mumsh) -> nix::Result<()> {
+    sh.signal_job(pgid, Signal::SIGCONT)?;
+    sh.jobs.get_mut(&pgid).unwrap().set_status(status);
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use crate::{types::CmdInfo, mumsh::Mumsh};
+
+/// break [n], continue [n], return [n]
+/// leaves, or goes on with the next round of, the nth enclosing loop
+/// return leaves the running function with status n, $? by default
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let name = cmd_info.tokens[0].1.clone();
+    if name == "return" {
+        return run_return(cmd_info, sh);
+    }
+    if sh.loop_depth == 0 {
+        eprintln!("{}: only meaningful in a `for', `while', or `until' loop", name);
+        return 0;
+    }
+    let mut n = 1;
+    if let Some(x) = cmd_info.tokens.get(1) {
+        match x.1.parse::<i32>() {
+            Ok(y) if y > 0 => n = y,
+            Ok(_) => {
+                eprintln!("{}: {}: loop count out of range", name, x.1);
+                return 1;
+            },
+            Err(_) => {
+                eprintln!("{}: numeric argument required: {}", name, x.1);
+                return 1;
+            }
+        };
+    }
+    // an n beyond the loops there are means the outermost one
+    let n = n.min(sh.loop_depth);
+    if name == "break" {
+        sh.breaking = n;
+    } else {
+        sh.continuing = n;
+    }
+    0
+}
+
+fn run_return(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    if sh.local_scopes.is_empty() {
+        eprintln!("return: can only `return' from a function");
+        return 2;
+    }
+    let status = match cmd_info.tokens.get(1) {
+        Some(x) => match x.1.parse::<i64>() {
+            Ok(y) => (y & 0xff) as i32,
+            Err(_) => {
+                eprintln!("return: {}: numeric argument required", x.1);
+                2
+            }
+        },
+        None => sh.last_status,
+    };
+    sh.returning = true;
+    status
+}
@@ -0,0 +1,75 @@
+use crate::{types::CmdInfo, mumsh::Mumsh};
+
+/// jobs [-l|-p|-r|-s|-v] [%job ...]
+/// lists the jobs, -l with their pids, -p only their pids, -r only running ones, -s only stopped ones
+/// -v with the time since they started and a line for each process, with its cpu times once done
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let mut with_pid = false;
+    let mut only_pid = false;
+    let mut only_running = false;
+    let mut only_stopped = false;
+    let mut verbose = false;
+    let mut specs = Vec::new();
+    for token in cmd_info.tokens.iter().skip(1) {
+        let arg = token.1.as_str();
+        if !arg.starts_with('-') || !specs.is_empty() {
+            specs.push(arg.to_string());
+            continue;
+        }
+        // options may be given together, eg: -lv
+        for c in arg.chars().skip(1) {
+            match c {
+                'l' => with_pid = true,
+                'p' => only_pid = true,
+                'r' => only_running = true,
+                's' => only_stopped = true,
+                'v' => verbose = true,
+                _ => {
+                    eprintln!("jobs: -{}: invalid option", c);
+                    eprintln!("jobs: usage: jobs [-l|-p|-r|-s|-v] [%job ...]");
+                    return 2;
+                }
+            };
+        }
+    }
+    sh.try_wait_bg_jobs();
+    let mut status = 0;
+    let mut pgids = Vec::new();
+    if specs.is_empty() {
+        pgids = sh.jobs.keys().copied().collect();
+        pgids.sort_by_key(|x| sh.jobs[x].id);
+    }
+    for spec in specs.iter() {
+        match sh.find_job(spec) {
+            Ok(x) => pgids.push(x),
+            Err(e) => {
+                eprintln!("jobs: {}", e);
+                status = 1;
+            }
+        };
+    }
+    for pgid in pgids {
+        let is_stopped = sh.jobs[&pgid].is_stopped();
+        let is_done = sh.jobs[&pgid].is_done();
+        if (only_running && (is_stopped || is_done)) || (only_stopped && !is_stopped) {
+            continue;
+        }
+        if only_pid {
+            println!("{}", pgid);
+        } else {
+            println!("{}", sh.job_line(pgid, with_pid, verbose));
+        }
+        if verbose && !only_pid {
+            for line in sh.jobs[&pgid].process_lines(true) {
+                println!("{}", line);
+            }
+        }
+        // listed is reported, done jobs leave the table
+        if is_done {
+            sh.remove_job(pgid);
+        } else {
+            sh.jobs.get_mut(&pgid).unwrap().set_reported();
+        }
+    }
+    status
+}
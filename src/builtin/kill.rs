@@ -0,0 +1,112 @@
+use std::str::FromStr;
+
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+use crate::{types::{CmdInfo, JobStatus}, mumsh::Mumsh};
+
+/// kill [-s sigspec | -n signum | -sigspec] pid | %job ...
+/// kill -l [signum ...]
+/// sends a signal, SIGTERM by default, to processes or to all processes of jobs
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let args: Vec<&str> = cmd_info.tokens.iter().skip(1).map(|x| x.1.as_str()).collect();
+    let mut signal = Signal::SIGTERM;
+    let mut targets = &args[..];
+    match args.first() {
+        Some(&"-l") | Some(&"-L") => return list_signals(&args[1..]),
+        Some(&"-s") | Some(&"-n") if args.len() > 1 => {
+            signal = match parse_signal(args[1]) {
+                Some(x) => x,
+                None => return invalid_signal(args[1]),
+            };
+            targets = &args[2..];
+        },
+        Some(&"--") => targets = &args[1..],
+        Some(x) if x.starts_with('-') && x.len() > 1 => {
+            signal = match parse_signal(&x[1..]) {
+                Some(y) => y,
+                None => return invalid_signal(&x[1..]),
+            };
+            targets = &args[1..];
+        },
+        _ => {},
+    };
+    if targets.is_empty() {
+        eprintln!("kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | %job ... or kill -l [sigspec]");
+        return 2;
+    }
+    let mut status = 0;
+    for target in targets {
+        let result = if target.starts_with('%') {
+            match sh.find_job(target) {
+                Ok(pgid) => {
+                    // a stopped job has to go on to see the signal, that is not reported as continued
+                    let is_stopped = sh.jobs[&pgid].is_stopped();
+                    sh.signal_job(pgid, signal).and_then(|_| match is_stopped && signal != Signal::SIGCONT {
+                        true => sh.signal_job(pgid, Signal::SIGCONT).map(|_| {
+                            sh.jobs.get_mut(&pgid).unwrap().set_status(JobStatus::Running);
+                        }),
+                        false => Ok(()),
+                    })
+                },
+                Err(e) => {
+                    eprintln!("kill: {}", e);
+                    status = 1;
+                    continue;
+                }
+            }
+        } else {
+            match target.parse::<i32>() {
+                Ok(x) => kill(Pid::from_raw(x), signal),
+                Err(_) => {
+                    eprintln!("kill: {}: arguments must be process or job IDs", target);
+                    status = 1;
+                    continue;
+                }
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("kill: ({}) - {}", target, e.desc());
+            status = 1;
+        }
+    }
+    status
+}
+
+/// a signal by name or number, eg: TERM, SIGTERM, term, 15
+fn parse_signal(spec: &str) -> Option<Signal> {
+    if let Ok(x) = spec.parse::<i32>() {
+        return Signal::try_from(x).ok();
+    }
+    let name = spec.to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    Signal::from_str(&format!("SIG{}", name)).ok()
+}
+
+fn invalid_signal(spec: &str) -> i32 {
+    eprintln!("kill: {}: invalid signal specification", spec);
+    1
+}
+
+/// all signal names, or the names of the numbers given, or the numbers of the names given
+fn list_signals(specs: &[&str]) -> i32 {
+    if specs.is_empty() {
+        let names: Vec<&str> = Signal::iterator().map(|x| &x.as_str()[3..]).collect();
+        println!("{}", names.join(" "));
+        return 0;
+    }
+    let mut status = 0;
+    for spec in specs {
+        // a status of a signaled process is 128 + the signal
+        let number = spec.parse::<i32>().map(|x| if x > 128 { x - 128 } else { x });
+        match (number, parse_signal(spec)) {
+            (Ok(x), _) => match Signal::try_from(x) {
+                Ok(y) => println!("{}", &y.as_str()[3..]),
+                Err(_) => status = invalid_signal(spec),
+            },
+            (Err(_), Some(x)) => println!("{}", x as i32),
+            (Err(_), None) => status = invalid_signal(spec),
+        };
+    }
+    status
+}
@@ -0,0 +1,36 @@
+use crate::{types::CmdInfo, mumsh::Mumsh, expand::param};
+
+/// local name[=value] ...
+/// the variables keep their values only until the running function returns,
+/// functions it calls see them too
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    if sh.local_scopes.is_empty() {
+        eprintln!("local: can only be used in a function");
+        return 1;
+    }
+    let mut status = 0;
+    for token in cmd_info.tokens.iter().skip(1) {
+        let (name, value) = match token.1.split_once('=') {
+            Some((x, y)) => (x, Some(y)),
+            None => (token.1.as_str(), None),
+        };
+        if !param::is_name(name) {
+            eprintln!("local: not a valid identifier: {}", token.1);
+            status = 1;
+            continue;
+        }
+        // only the value from before the first local is given back
+        let saved = sh.save_var(name);
+        let scope = sh.local_scopes.last_mut().unwrap();
+        let is_new = !scope.contains_key(name);
+        if is_new {
+            scope.insert(name.to_string(), saved);
+        }
+        match value {
+            Some(x) => sh.set_var(name, x),
+            None if is_new => sh.unset_var(name),
+            None => {}
+        };
+    }
+    status
+}
@@ -1,2 +1,31 @@
 pub mod cd;
-pub mod which;
\ No newline at end of file
+pub mod disown;
+pub mod exit;
+pub mod export;
+pub mod fg;
+pub mod flow_control;
+pub mod jobs;
+pub mod kill;
+pub mod local;
+pub mod set;
+pub mod shopt;
+pub mod unset;
+pub mod wait;
+pub mod which;
+
+/// names of all builtin commands
+pub const BUILTINS: [&str; 17] = [
+    "bg", "break", "cd", "continue", "disown", "exit", "export", "fg", "jobs", "kill", "local",
+    "return", "set", "shopt", "unset", "wait", "which",
+];
+
+/// builtins whose prefix assignments stay after them, as POSIX wants
+pub const SPECIAL_BUILTINS: [&str; 7] = ["break", "continue", "exit", "export", "return", "set", "unset"];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+pub fn is_special_builtin(name: &str) -> bool {
+    SPECIAL_BUILTINS.contains(&name)
+}
@@ -0,0 +1,49 @@
+use crate::{types::CmdInfo, mumsh::Mumsh};
+
+/// set [-b|+b] [-C|+C] [-o|+o [optname]]
+/// an option is turned on by - and off by +, -b is -o notify, -C is -o noclobber
+/// -o and +o without a name print the options
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let mut args = cmd_info.tokens.iter().skip(1).map(|x| x.1.as_str());
+    while let Some(arg) = args.next() {
+        let value = arg.starts_with('-');
+        match arg {
+            "-b" | "+b" => {
+                sh.set_options.insert(String::from("notify"), value);
+            },
+            "-C" | "+C" => {
+                sh.set_options.insert(String::from("noclobber"), value);
+            },
+            "-o" | "+o" => match args.next() {
+                Some(name) if sh.set_options.contains_key(name) => {
+                    sh.set_options.insert(name.to_string(), value);
+                },
+                Some(name) => {
+                    eprintln!("set: {}: invalid option name", name);
+                    return 1;
+                },
+                None => print_options(value, sh),
+            },
+            _ => {
+                eprintln!("set: {}: invalid option", arg);
+                eprintln!("set: usage: set [-b|+b] [-C|+C] [-o|+o [optname]]");
+                return 2;
+            }
+        };
+    }
+    0
+}
+
+/// as a table for -o, as commands for +o
+fn print_options(as_table: bool, sh: &Mumsh) {
+    let mut names: Vec<&String> = sh.set_options.keys().collect();
+    names.sort();
+    for name in names {
+        let value = sh.get_option(name);
+        if as_table {
+            println!("{:<15} {}", name, if value { "on" } else { "off" });
+        } else {
+            println!("set {}o {}", if value { "-" } else { "+" }, name);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{types::CmdInfo, mumsh::Mumsh};
+
+/// shopt [-s|-u|-p|-q] [optname ...]
+/// -s sets, -u unsets, -q only reports with the status, otherwise options are printed
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let mut set: Option<bool> = None;
+    let mut quiet = false;
+    let mut print_as_cmd = false;
+    let mut names = Vec::new();
+    for token in cmd_info.tokens.iter().skip(1) {
+        match token.1.as_str() {
+            "-s" => set = Some(true),
+            "-u" => set = Some(false),
+            "-q" => quiet = true,
+            "-p" => print_as_cmd = true,
+            x if x.starts_with('-') => {
+                eprintln!("shopt: {}: invalid option", x);
+                eprintln!("shopt: usage: shopt [-s|-u|-p|-q] [optname ...]");
+                return 2;
+            },
+            x => names.push(x.to_string()),
+        };
+    }
+    for name in names.iter() {
+        if !sh.options.contains_key(name) {
+            eprintln!("shopt: {}: invalid shell option name", name);
+            return 1;
+        }
+    }
+    if let Some(x) = set {
+        for name in names.iter() {
+            sh.options.insert(name.clone(), x);
+        }
+        if !names.is_empty() {
+            return 0;
+        }
+    }
+    if names.is_empty() {
+        names = sh.options.keys().cloned().collect();
+        names.sort();
+    }
+    let mut status = 0;
+    for name in names {
+        let value = sh.get_option(&name);
+        // with -s or -u and no names, only the options in that state are listed
+        if set.is_some() && set != Some(value) {
+            continue;
+        }
+        if !value {
+            status = 1;
+        }
+        if quiet {
+            continue;
+        }
+        if print_as_cmd {
+            println!("shopt {} {}", if value { "-s" } else { "-u" }, name);
+        } else {
+            println!("{:<15} {}", name, if value { "on" } else { "off" });
+        }
+    }
+    status
+}
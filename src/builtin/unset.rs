@@ -0,0 +1,28 @@
+use crate::{types::CmdInfo, mumsh::Mumsh, expand::param};
+
+/// unset [-f] name ...
+/// removes shell variables and environment variables, or functions with -f
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    let mut status = 0;
+    let mut is_function = false;
+    for token in cmd_info.tokens.iter().skip(1) {
+        if token.1 == "-v" {
+            continue;
+        }
+        if token.1 == "-f" {
+            is_function = true;
+            continue;
+        }
+        if is_function {
+            sh.functions.remove(&token.1);
+            continue;
+        }
+        if !param::is_name(&token.1) {
+            eprintln!("unset: not a valid identifier: {}", token.1);
+            status = 1;
+            continue;
+        }
+        sh.unset_var(&token.1);
+    }
+    status
+}
@@ -0,0 +1,53 @@
+use crate::{types::CmdInfo, mumsh::Mumsh};
+
+/// wait [%job | pid ...]
+/// waits for the jobs or processes given, or for all running jobs,
+/// returns the status of the last one, 127 if it is unknown
+pub fn run(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> i32 {
+    if cmd_info.tokens.len() == 1 {
+        let mut pgids: Vec<i32> = sh.jobs.keys().copied().collect();
+        pgids.sort_by_key(|x| sh.jobs[x].id);
+        for pgid in pgids {
+            // stopped jobs would never be done
+            if !sh.jobs[&pgid].is_stopped() {
+                sh.wait_job(pgid);
+            }
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for token in cmd_info.tokens.iter().skip(1) {
+        let spec = token.1.as_str();
+        if spec.starts_with('%') {
+            status = match sh.find_job(spec) {
+                Ok(x) => sh.wait_job(x),
+                Err(e) => {
+                    eprintln!("wait: {}", e);
+                    127
+                }
+            };
+            continue;
+        }
+        let pid = match spec.parse::<i32>() {
+            Ok(x) => x,
+            Err(_) => {
+                eprintln!("wait: `{}': not a pid or valid job spec", spec);
+                status = 2;
+                continue;
+            }
+        };
+        let pgid = match sh.jobs.values().find(|x| x.pids.contains(&pid)) {
+            Some(x) => x.pgid,
+            None => {
+                eprintln!("wait: pid {} is not a child of this shell", pid);
+                status = 127;
+                continue;
+            }
+        };
+        status = sh.wait_pid(pgid, pid);
+        if sh.jobs[&pgid].pids.is_empty() {
+            sh.reap_job(pgid);
+        }
+    }
+    status
+}
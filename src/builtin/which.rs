@@ -1,4 +1,4 @@
-use crate::{types::CmdInfo, mumsh::Mumsh};
+use crate::{types::CmdInfo, mumsh::Mumsh, builtin};
 
 // returns 0 if all commands are builtin, returns -1 if need to run which
 pub fn run(cmd_info: &mut CmdInfo, _sh: &mut Mumsh) -> i32 {
@@ -8,7 +8,7 @@ pub fn run(cmd_info: &mut CmdInfo, _sh: &mut Mumsh) -> i32 {
     }
     let mut to_delete = Vec::new();
     for (i, token) in cmd_info.tokens.iter_mut().skip(1).enumerate() {
-        if token.1 == "cd" || token.1 == "which" {
+        if builtin::is_builtin(&token.1) {
             println!("{}: shell built-in command", token.1);
             to_delete.push(i+1);
         }
@@ -1,4 +1,9 @@
 use std::env::current_dir;
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
 
 pub fn get_current_dir() -> String {
     match current_dir() {
@@ -6,4 +11,44 @@ pub fn get_current_dir() -> String {
         Err(e) => eprintln!("Error getting current directory: {}", e),
     }
     String::new()
-}
\ No newline at end of file
+}
+/// convert the result of waitpid into an exit status
+/// signaled processes get 128 + signal number, like other shells do
+pub fn wait_status_to_code(status: &WaitStatus) -> i32 {
+    match status {
+        WaitStatus::Exited(_, x) => *x,
+        WaitStatus::Signaled(_, signal, _) => 128 + *signal as i32,
+        WaitStatus::Stopped(_, signal) => 128 + *signal as i32,
+        _ => 0,
+    }
+}
+
+/// give a forked child the default SIGPIPE back
+/// rust ignores it at startup, the commands a child runs expect it to kill them
+pub fn reset_sigpipe() {
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+}
+
+/// waitpid that also gives the user and system time a child used once it is done
+pub fn wait4(pid: Pid, options: Option<WaitPidFlag>) -> nix::Result<(WaitStatus, (Duration, Duration))> {
+    let mut status = 0;
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    let options = options.map_or(0, |x| x.bits());
+    let res = Errno::result(unsafe { libc::wait4(pid.as_raw(), &mut status, options, &mut usage) })?;
+    let to_duration = |x: libc::timeval| Duration::new(x.tv_sec as u64, x.tv_usec as u32 * 1000);
+    let times = (to_duration(usage.ru_utime), to_duration(usage.ru_stime));
+    match res {
+        0 => Ok((WaitStatus::StillAlive, times)),
+        x => Ok((WaitStatus::from_raw(Pid::from_raw(x), status)?, times)),
+    }
+}
+
+/// a duration in its largest unit, eg: 42s, 12m, 3h
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h", secs / 3600),
+    }
+}
@@ -1,22 +1,71 @@
+use std::collections::HashMap;
 use std::ffi::{CString, CStr};
+use std::io::{self, Write};
+use std::process;
+use std::time::Instant;
 
-use crate::builtin;
-use crate::{parser, mumsh::Mumsh};
-use crate::types::{CmdlineInfo, CmdInfo};
+use crate::{builtin, common};
+use crate::{parser::{self, ParseError}, mumsh::Mumsh};
+use crate::parser::ast::{AndOr, AndOrOp, Command, CompoundCommand, List, Pipeline};
+use crate::types::{CmdlineInfo, CmdInfo, JobStatus, Redir, RedirType, Token, Tokens};
+use crate::expand::{self, pattern::Pattern};
 
-use nix::sys::stat::Mode;
-use nix::unistd::{dup2, pipe, fork, execvp, close, getpid, setpgid, ForkResult, Pid, getpgid};
-use nix::sys::wait::wait;
-use nix::fcntl::{open, OFlag};
+use nix::sys::stat::{stat, Mode, SFlag};
+use nix::unistd::{dup2, pipe, fork, execvp, close, getpid, setpgid, ForkResult, Pid, mkstemp, unlink, write, lseek, Whence};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::fcntl::{fcntl, open, FcntlArg, OFlag};
+
+/// how deep functions may call each other, deeper calls fail instead of overflowing the stack
+const MAX_FUNCTION_DEPTH: usize = 256;
 
 pub fn try_run_builtin(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> Option<i32> {
     let token_first = match cmd_info.tokens.first() {
         Some(x) => x,
         None => return None
     };
+    // functions come before builtins and PATH
+    if let Some(x) = sh.functions.get(&token_first.1).cloned() {
+        return Some(run_function(&x, cmd_info, sh));
+    }
+    if token_first.1 == "break" || token_first.1 == "continue" || token_first.1 == "return" {
+        return Some(builtin::flow_control::run(cmd_info, sh));
+    }
+    if token_first.1 == "bg" || token_first.1 == "fg" {
+        return Some(builtin::fg::run(cmd_info, sh));
+    }
     if token_first.1 == "cd" {
         return Some(builtin::cd::run(cmd_info, sh));
     }
+    if token_first.1 == "disown" {
+        return Some(builtin::disown::run(cmd_info, sh));
+    }
+    if token_first.1 == "exit" {
+        return Some(builtin::exit::run(cmd_info, sh));
+    }
+    if token_first.1 == "export" {
+        return Some(builtin::export::run(cmd_info, sh));
+    }
+    if token_first.1 == "jobs" {
+        return Some(builtin::jobs::run(cmd_info, sh));
+    }
+    if token_first.1 == "kill" {
+        return Some(builtin::kill::run(cmd_info, sh));
+    }
+    if token_first.1 == "local" {
+        return Some(builtin::local::run(cmd_info, sh));
+    }
+    if token_first.1 == "set" {
+        return Some(builtin::set::run(cmd_info, sh));
+    }
+    if token_first.1 == "shopt" {
+        return Some(builtin::shopt::run(cmd_info, sh));
+    }
+    if token_first.1 == "unset" {
+        return Some(builtin::unset::run(cmd_info, sh));
+    }
+    if token_first.1 == "wait" {
+        return Some(builtin::wait::run(cmd_info, sh));
+    }
     if token_first.1 == "which" {
         if builtin::which::run(cmd_info, sh) == 0 {
             return Some(0);
@@ -25,33 +74,177 @@ pub fn try_run_builtin(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> Option<i32> {
     None
 }
 
-/// run an entire line
-pub fn run(line: &str, sh: &mut Mumsh) {
+/// run a script, used for mumsh -c, mumsh script.sh and command substitutions
+pub fn run_script(script: &str, sh: &mut Mumsh) -> i32 {
+    let mut lines = script.lines().enumerate()
+                          .filter(|(i, x)| *i > 0 || !x.starts_with("#!"))
+                          .map(|x| x.1.to_string());
+    run_lines(&mut lines, sh)
+}
+
+/// run the commands of a script read line by line, each complete command is run as soon as it is read
+/// used for run_script and piped stdin
+pub fn run_lines(lines: &mut dyn Iterator<Item = String>, sh: &mut Mumsh) -> i32 {
+    let mut parser = parser::script_parser(lines);
     let mut status = 0;
-    for token in parser::parse_line::split_line(line) {
-        if token == "&&" && status != 0 {
+    loop {
+        match parser.parse_line() {
+            Ok(Some(list)) => status = run_list(&list, sh),
+            Ok(None) => return status,
+            Err(ParseError::Incomplete(_)) => {
+                eprintln!("mumsh: parse error: unexpected end of file");
+                return 2;
+            },
+            Err(ParseError::Syntax(e)) => {
+                eprintln!("mumsh: parse error near `{}\'", e);
+                return 2;
+            },
+        };
+    }
+}
+
+/// run an entire line, no more input follows it
+pub fn run(line: &str, sh: &mut Mumsh) -> i32 {
+    // interactive_comments decides whether # starts a comment when interactive
+    let comments = !sh.is_interactive || sh.get_option("interactive_comments");
+    let status = match parser::parse(line, true, comments) {
+        Ok(list) => return run_list(&list, sh),
+        Err(ParseError::Incomplete(_)) => {
+            eprintln!("mumsh: parse error: unexpected end of file");
+            2
+        },
+        Err(ParseError::Syntax(e)) => {
+            eprintln!("mumsh: parse error near `{}\'", e);
+            2
+        },
+    };
+    sh.last_status = status;
+    status
+}
+
+/// run the and-or lists one after another, background ones are not waited for
+pub fn run_list(list: &List, sh: &mut Mumsh) -> i32 {
+    let mut status = sh.last_status;
+    for item in list.items.iter() {
+        if is_interrupted(sh) {
             break;
         }
-        if token == "||" && status == 0 {
+        status = if item.is_background {
+            run_background(&item.and_or, sh)
+        } else {
+            run_and_or(&item.and_or, sh)
+        };
+        sh.last_status = status;
+    }
+    status
+}
+
+/// whether break, continue or return stops the commands left in a list
+fn is_interrupted(sh: &Mumsh) -> bool {
+    sh.breaking > 0 || sh.continuing > 0 || sh.returning
+}
+
+/// a && b: b runs only if a succeeded, a || b: b runs only if a failed
+fn run_and_or(and_or: &AndOr, sh: &mut Mumsh) -> i32 {
+    let mut status = run_cmdline(&and_or.first, false, sh);
+    sh.last_status = status;
+    for (op, pipeline) in and_or.rest.iter() {
+        if is_interrupted(sh) {
             break;
         }
-        if token == "||" || token == "&&" || token == ";" {
-            continue;
+        if (*op == AndOrOp::And) == (status == 0) {
+            status = run_cmdline(pipeline, false, sh);
+            sh.last_status = status;
+        }
+    }
+    status
+}
+
+/// run an and-or list in background, a single pipeline becomes a job by itself,
+/// a longer list runs in a forked mumsh
+fn run_background(and_or: &AndOr, sh: &mut Mumsh) -> i32 {
+    if and_or.rest.is_empty() {
+        return run_cmdline(&and_or.first, true, sh);
+    }
+    match unsafe{fork()} {
+        Ok(ForkResult::Parent { child, .. }) => {
+            if sh.is_interactive {
+                setpgid(child, child).ok();
+            }
+            sh.insert_job(child.as_raw(), child.as_raw(), &and_or.text(), &and_or.text());
+            sh.last_bg_pid = child.as_raw();
+            if sh.is_interactive {
+                sh.print_job(child.as_raw());
+            }
+            0
+        },
+        Ok(ForkResult::Child) => {
+            if sh.is_interactive {
+                setpgid(Pid::from_raw(0), getpid()).ok();
+            }
+            sh.is_interactive = false;
+            sh.jobs.clear();
+            sh.job_order.clear();
+            let status = run_and_or(and_or, sh);
+            io::stdout().flush().ok();
+            unsafe { libc::_exit(status) };
+        },
+        Err(_) => {
+            eprintln!("mumsh: fork failed");
+            1
         }
-        status = run_cmdline(&token, sh);
     }
 }
 
 /// run a sigle commandline that contains pipes
-pub fn run_cmdline(cmd: &str, sh: &mut Mumsh) -> i32 {
-    let mut cmdline_info = match CmdlineInfo::from(cmd) {
-        Ok(x) => x,
-        Err(e) => {
-            eprintln!("mumsh: {}", e);
-            return -1;      // TODO: what to return?
+/// returns the exit status of the last command, 0 if run in background
+pub fn run_cmdline(pipeline: &Pipeline, is_background: bool, sh: &mut Mumsh) -> i32 {
+    let mut cmdline_info = CmdlineInfo::from(pipeline, is_background);
+    // name() compound-command, defines a function
+    if let [Command::FunctionDef(name, body)] = pipeline.cmds.as_slice() {
+        sh.functions.insert(name.clone(), body.clone());
+        return 0;
+    }
+    // a compound command alone in foreground runs in mumsh herself, unless it is a subshell
+    if let [cmd_info] = cmdline_info.cmds.as_mut_slice() {
+        if !is_background && matches!(&cmd_info.compound, Some(x) if !matches!(x, CompoundCommand::Subshell(_))) {
+            if let Err(e) = expand_cmd(cmd_info, sh) {
+                finish_proc_substs(None, sh);
+                return expansion_error(&e, sh);
+            }
+            let compound = cmd_info.compound.as_ref().unwrap();
+            let status = run_in_shell(&cmd_info.redirects, sh, |sh| run_compound(compound, sh)).unwrap_or(1);
+            finish_proc_substs(None, sh);
+            return status;
         }
-    };
+    }
     let cmd_num = cmdline_info.cmds.len();
+    if cmd_num == 0 {
+        return sh.last_status;
+    }
+    // NAME=value ..., set shell variables, in background they only go to the subshell
+    let cmd_first = &cmdline_info.cmds[0];
+    if cmd_num == 1 && !is_background && cmd_first.tokens.is_empty() && !cmd_first.assignments.is_empty() {
+        sh.subst_status = None;
+        for token in cmd_first.assignments.iter() {
+            let (name, value) = token.1.split_once('=').unwrap();
+            let value = match expand::expand_assignment(&(token.0.clone(), value.to_string()), sh) {
+                Ok(x) => x,
+                Err(e) => return expansion_error(&e, sh),
+            };
+            sh.set_var(name, &value);
+        }
+        finish_proc_substs(None, sh);
+        // the status is the one of the last command substitution, if any
+        return sh.subst_status.unwrap_or(0);
+    }
+    // expand words, redirection targets and heredocs
+    for cmd_info in cmdline_info.cmds.iter_mut() {
+        if let Err(e) = expand_cmd(cmd_info, sh) {
+            finish_proc_substs(None, sh);
+            return expansion_error(&e, sh);
+        }
+    }
     // parent: create all pipes and store in vec_pipes: pipe[0] read, pipe[1] write
     let mut vec_pipes = Vec::new();
     for _ in 0..cmd_num-1 {
@@ -59,60 +252,368 @@ pub fn run_cmdline(cmd: &str, sh: &mut Mumsh) -> i32 {
             Ok(x) => vec_pipes.push(x),
             Err(e) => {
                 eprintln!("mumsh: pipe error {}", e);
-                return -1;
+                return 1;
             }
         };
     }
+    let start = Instant::now();
+    // with job control the subshells of process substitutions lead the group, see process_substitute
+    let mut pgid = if sh.is_interactive { sh.proc_subst_pids.first().copied().unwrap_or(0) } else { 0 };
     let mut pid_first_child = 0;
-    let mut cmds_to_wait = 0;
+    let mut pids_to_wait = Vec::new();
+    let mut argvs = HashMap::new();
+    let mut pid_last_child = 0;
     for (i, cmd) in cmdline_info.cmds.iter_mut().enumerate() {
-        let pid_child = run_single_cmd(cmd, cmd_num, i, &vec_pipes, sh, pid_first_child);
+        let pid_child = run_single_cmd(cmd, cmd_num, i, &vec_pipes, sh, pgid, cmdline_info.is_background);
+        pid_last_child = pid_child;
+        if pid_child <= 0 {
+            continue;       // run in mumsh herself or not forked at all, there is no process for a job
+        }
         if pid_first_child == 0 {
             pid_first_child = pid_child;
         }
-        if pid_child > 0 && !cmdline_info.is_background {
-            cmds_to_wait += 1;
+        if pgid == 0 {
+            pgid = pid_child;
         }
         if cmdline_info.is_background {
-            sh.insert_job(pid_first_child, pid_child);
+            sh.insert_job(pgid, pid_child, &cmdline_info.line, &cmd.argv());
+        } else {
+            pids_to_wait.push(pid_child);
+            argvs.insert(pid_child, cmd.argv());
         }
     }
     // donate tty to child
-    if pid_first_child != 0 && !cmdline_info.is_background {
-        sh.set_foreground_pg(pid_first_child);
+    if pid_first_child != 0 && !cmdline_info.is_background && sh.is_interactive {
+        sh.set_foreground_pg(pgid);
     }
     // background
-    sh.print_job(pid_first_child);
+    if sh.is_interactive && pgid > 0 {
+        sh.print_job(pgid);
+    }
     // remember to close all unused pipes, otherwise EOF might be missed!
+    // the read ends too, or a writer whose reader is gone never gets SIGPIPE, eg: yes | head -1
     for pipe in &vec_pipes {
+        close(pipe.0).expect("Error closing pipe 0");
         close(pipe.1).expect("Error closing pipe 1");
     }
-    for _ in 0..cmds_to_wait {
-        wait().unwrap();    // TODO: background? unwrap panic?
+    // the subshells of process substitutions go with a background job, in foreground they are waited for below
+    if cmdline_info.is_background {
+        finish_proc_substs(Some(pgid), sh);
     }
-    // reclaim tty
-    if pid_first_child != 0 && !cmdline_info.is_background {
-        let pgid = getpgid(Some(Pid::from_raw(0))).expect("Error getting pgid").as_raw();
-        sh.set_foreground_pg(pgid);
+    for fd in std::mem::take(&mut sh.proc_subst_fds) {
+        close(fd).ok();
     }
-    for pipe in &vec_pipes {
-        close(pipe.0).expect("Error closing pipe 0");
+    // the status of a pipeline is the status of its last command
+    let mut status = 0;
+    if pid_last_child == 0 {
+        status = sh.last_status;    // last command is a builtin
+    }
+    while !pids_to_wait.is_empty() {
+        // with job control the whole group is waited for, any of its processes may get stopped
+        let (pid, options) = match sh.is_interactive {
+            true => (-pgid, Some(WaitPidFlag::WUNTRACED)),
+            false => (pids_to_wait[0], None),
+        };
+        match waitpid(Pid::from_raw(pid), options) {
+            Ok(WaitStatus::Stopped(_, signal)) => {
+                // eg: ctrl-z, the processes left become a stopped job
+                for pid in pids_to_wait.drain(..) {
+                    sh.insert_job(pgid, pid, &cmdline_info.line, &argvs[&pid]);
+                    sh.jobs.get_mut(&pgid).unwrap().status.insert(pid, JobStatus::Stopped);
+                }
+                sh.jobs.get_mut(&pgid).unwrap().start = start;
+                sh.suspend_job(pgid);
+                status = 128 + signal as i32;
+            },
+            Ok(x) => {
+                // may be a subshell of a process substitution, that is all then
+                let pid = x.pid().map(|y| y.as_raw()).unwrap_or(pid);
+                pids_to_wait.retain(|y| *y != pid);
+                if pid == pid_last_child {
+                    status = common::wait_status_to_code(&x);
+                }
+            },
+            Err(nix::Error::EINTR) => {},
+            Err(_) => {
+                status = 1;
+                break;
+            }
+        };
+    }
+    // a stopped job takes the subshells along
+    if !cmdline_info.is_background {
+        finish_proc_substs(Some(pgid), sh);
+    }
+    if cmdline_info.is_background {
+        sh.last_bg_pid = pid_last_child;
+        status = 0;
+    }
+    if pid_first_child != 0 && !cmdline_info.is_background && sh.is_interactive {
+        sh.reclaim_terminal();
     }
-    0
+    status
+}
+
+/// close the fds of process substitutions once the commands that use them are forked
+/// their subshells go with the job of pgid if there is one, a background or stopped one
+/// otherwise a script waits for them, an interactive mumsh only reaps the ones done,
+/// the others are reaped later like disowned jobs, eg: echo <(sleep 30)
+fn finish_proc_substs(pgid: Option<i32>, sh: &mut Mumsh) {
+    for fd in std::mem::take(&mut sh.proc_subst_fds) {
+        close(fd).ok();
+    }
+    let pids = std::mem::take(&mut sh.proc_subst_pids);
+    if let Some(job) = pgid.and_then(|x| sh.jobs.get_mut(&x)) {
+        job.helpers.extend(pids);
+        return;
+    }
+    for pid in pids {
+        if !sh.is_interactive {
+            while let Err(nix::Error::EINTR) = waitpid(Pid::from_raw(pid), None) {}
+        } else if let Ok(WaitStatus::StillAlive) = waitpid(Pid::from_raw(pid), Some(WaitPidFlag::WNOHANG)) {
+            sh.disowned.push(pid);
+        }
+    }
+}
+
+/// run a compound command in the current shell
+/// a subshell runs its list right here too, the caller has to fork first
+fn run_compound(compound: &CompoundCommand, sh: &mut Mumsh) -> i32 {
+    match compound {
+        CompoundCommand::Arith(x) => run_arith_cmd(x, sh),
+        CompoundCommand::BraceGroup(x) | CompoundCommand::Subshell(x) => run_list(x, sh),
+        CompoundCommand::If(branches, else_body) => {
+            for (condition, body) in branches.iter() {
+                if run_list(condition, sh) == 0 {
+                    return run_list(body, sh);
+                }
+            }
+            match else_body {
+                Some(x) => run_list(x, sh),
+                None => 0,
+            }
+        },
+        CompoundCommand::While(condition, body) => run_loop(condition, body, true, sh),
+        CompoundCommand::Until(condition, body) => run_loop(condition, body, false, sh),
+        CompoundCommand::For(name, words, body) => {
+            let words = match words {
+                Some(x) => {
+                    let tokens: Vec<Token> = x.iter().map(|y| y.to_token()).collect();
+                    match expand::expand_tokens(&tokens, sh) {
+                        Ok(y) => y.into_iter().map(|z| z.1).collect(),
+                        Err(e) => return expansion_error(&e, sh),
+                    }
+                },
+                None => sh.args[1..].to_vec(),
+            };
+            let mut status = 0;
+            sh.loop_depth += 1;
+            for word in words {
+                sh.set_var(name, &word);
+                status = run_list(body, sh);
+                if loop_control_done(sh) {
+                    break;
+                }
+            }
+            sh.loop_depth -= 1;
+            status
+        },
+        CompoundCommand::Case(word, items) => {
+            let word = match expand::expand_token_string(&word.to_token(), sh) {
+                Ok(x) => x,
+                Err(e) => return expansion_error(&e, sh),
+            };
+            for item in items.iter() {
+                for pattern in item.patterns.iter() {
+                    let pattern = match expand::expand_pattern(&pattern.to_token(), sh) {
+                        Ok(x) => x,
+                        Err(e) => return expansion_error(&e, sh),
+                    };
+                    if Pattern::new(&pattern).matches(&word) {
+                        return run_list(&item.body, sh);
+                    }
+                }
+            }
+            0
+        },
+    }
+}
+
+/// while and until loops, the body runs as long as the condition succeeds, or fails for until
+fn run_loop(condition: &List, body: &List, is_while: bool, sh: &mut Mumsh) -> i32 {
+    let mut status = 0;
+    sh.loop_depth += 1;
+    loop {
+        let condition_status = run_list(condition, sh);
+        if loop_control_done(sh) || (condition_status == 0) != is_while {
+            break;
+        }
+        status = run_list(body, sh);
+        if loop_control_done(sh) {
+            break;
+        }
+    }
+    sh.loop_depth -= 1;
+    status
+}
+
+/// after a round of a loop, whether break or continue ends the loop
+fn loop_control_done(sh: &mut Mumsh) -> bool {
+    if sh.returning {
+        return true;
+    }
+    if sh.breaking > 0 {
+        sh.breaking -= 1;
+        return true;
+    }
+    if sh.continuing > 0 {
+        // continue n: n-1 inner loops end, the nth one goes on
+        sh.continuing -= 1;
+        return sh.continuing > 0;
+    }
+    false
+}
+
+/// run a function with the arguments as its positional parameters,
+/// the variables it makes local get their values back when it returns
+fn run_function(body: &Command, cmd_info: &CmdInfo, sh: &mut Mumsh) -> i32 {
+    if sh.local_scopes.len() >= MAX_FUNCTION_DEPTH {
+        eprintln!("mumsh: {}: maximum function nesting level exceeded ({})", cmd_info.tokens[0].1, MAX_FUNCTION_DEPTH);
+        return 1;
+    }
+    let mut args = vec![sh.args[0].clone()];
+    args.extend(cmd_info.tokens.iter().skip(1).map(|x| x.1.clone()));
+    let args_saved = std::mem::replace(&mut sh.args, args);
+    // break and continue do not reach the loops of the caller
+    let loop_depth_saved = std::mem::replace(&mut sh.loop_depth, 0);
+    sh.local_scopes.push(HashMap::new());
+    let status = match body {
+        Command::Compound(x, redirects) if redirects.is_empty() && !matches!(x, CompoundCommand::Subshell(_)) => run_compound(x, sh),
+        _ => run_cmdline(&Pipeline { cmds: vec![body.clone()], text: String::new() }, false, sh),
+    };
+    sh.returning = false;
+    for (name, saved) in sh.local_scopes.pop().unwrap_or_default() {
+        sh.restore_var(&name, saved);
+    }
+    sh.loop_depth = loop_depth_saved;
+    sh.args = args_saved;
+    status
+}
+
+/// report an expansion error, it is fatal to scripts
+fn expansion_error(e: &str, sh: &Mumsh) -> i32 {
+    eprintln!("mumsh: {}", e);
+    if !sh.is_interactive {
+        process::exit(1);
+    }
+    1
+}
+
+/// run (( expr )), returns 0 if expr is not zero, 1 otherwise
+fn run_arith_cmd(expr: &str, sh: &mut Mumsh) -> i32 {
+    match expand::expand_arith(expr, sh) {
+        Ok(0) => 1,
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("mumsh: {}", e);
+            1
+        }
+    }
+}
+
+/// expand everything in a command before it is run
+fn expand_cmd(cmd_info: &mut CmdInfo, sh: &mut Mumsh) -> Result<(), String> {
+    cmd_info.tokens = expand::expand_tokens(&cmd_info.tokens, sh)?;
+    cmd_info.assignments = expand_prefix_assignments(&cmd_info.assignments, sh)?;
+    for redirect in cmd_info.redirects.iter_mut() {
+        if redirect.redir_type == RedirType::HereDoc {
+            if redirect.word.0.is_empty() {     // quoted delimiter, no expansion
+                redirect.here_doc = expand::expand_here_doc(&redirect.here_doc, sh)?;
+            }
+        } else {
+            redirect.word.1 = expand::expand_token_string(&redirect.word, sh)?;
+        }
+    }
+    Ok(())
+}
+
+/// expand the NAME=value before a command, after its words, each one is seen by the next ones
+/// eg: a=1 b=$a => ["a=1", "b=1"], mumsh has her variables back after
+fn expand_prefix_assignments(assignments: &[Token], sh: &mut Mumsh) -> Result<Tokens, String> {
+    let mut expanded = Vec::new();
+    let mut saved = Vec::new();
+    let result = assignments.iter().try_for_each(|token| {
+        let (name, value) = token.1.split_once('=').unwrap_or_default();
+        let value = expand::expand_assignment(&(token.0.clone(), value.to_string()), sh)?;
+        saved.push((name, sh.save_var(name)));
+        sh.set_var(name, &value);
+        expanded.push((String::new(), format!("{}={}", name, value)));
+        Ok(())
+    });
+    for (name, x) in saved.into_iter().rev() {
+        sh.restore_var(name, x);
+    }
+    result.map(|_| expanded)
 }
 
 /// run a single command, without pipes, but with redirections
-pub fn run_single_cmd(cmd_info: &mut CmdInfo, cmd_num: usize, cmd_idx: usize, pipes: &Vec<(i32, i32)>, sh: &mut Mumsh, pgid: i32) -> i32 {
+pub fn run_single_cmd(cmd_info: &mut CmdInfo, cmd_num: usize, cmd_idx: usize, pipes: &Vec<(i32, i32)>, sh: &mut Mumsh, pgid: i32, is_background: bool) -> i32 {
     // fork
     let dup_error = "mumsh: error duplicating file descriptor";
     let close_error = "mumsh: error closing file descriptor";
     let cstring_error = "mumsh: error creating cstring";
-    // check if builtin, return 0 if builtin
-    if let Some(_) = try_run_builtin(cmd_info, sh) {
+    // builtins and functions run in mumsh herself, return 0 then, the status goes to sh.last_status
+    // in a pipeline or in background they are forked like the other commands
+    if cmd_num == 1 && !is_background && is_builtin_or_function(cmd_info, sh) {
+        // prefix assignments stay after special builtins, others only see them while they run
+        let name = &cmd_info.tokens[0].1;
+        let is_special = builtin::is_special_builtin(name) && !sh.functions.contains_key(name);
+        let mut saved = Vec::new();
+        for (name, value) in cmd_info.assignments.iter().filter_map(|x| x.1.split_once('=')) {
+            if is_special {
+                sh.set_var(name, value);
+            } else {
+                saved.push((name.to_string(), sh.save_var(name)));
+                sh.export_var(name, Some(value));
+            }
+        }
+        let redirects = std::mem::take(&mut cmd_info.redirects);
+        let result = run_in_shell(&redirects, sh, |sh| try_run_builtin(cmd_info, sh));
+        cmd_info.redirects = redirects;
+        for (name, x) in saved.into_iter().rev() {
+            sh.restore_var(&name, x);
+        }
+        match result {
+            Some(Some(x)) => {
+                sh.last_status = x;
+                return 0;
+            },
+            Some(None) => {},       // which found nothing, the command of that name is run
+            None => {
+                sh.last_status = 1;
+                return 0;
+            }
+        };
+    }
+    // {name}>file is opened by mumsh herself, so that name is set and the fd stays open after the command
+    // a pipeline or a background job runs in subshells, the fd and name are theirs
+    let (fd_var_redirects, redirects): (Vec<Redir>, Vec<Redir>) = if cmd_num == 1 && !is_background {
+        cmd_info.redirects.iter().cloned().partition(|x| x.fd_var.is_some())
+    } else {
+        (Vec::new(), cmd_info.redirects.clone())
+    };
+    if let Err(e) = apply_redirects(&fd_var_redirects, sh) {
+        eprintln!("mumsh: {}", e);
+        sh.last_status = 1;
         return 0;
     }
     match unsafe{fork()} {
         Ok(ForkResult::Parent { child, .. }) => {
+            // also set the pgid from the parent, so that later commands can join the group
+            if sh.is_interactive {
+                let pgid_child = if pgid == 0 { child } else { Pid::from_raw(pgid) };
+                setpgid(child, pgid_child).ok();
+            }
             return child.as_raw();
         }
         Ok(ForkResult::Child) => {
@@ -124,43 +625,16 @@ pub fn run_single_cmd(cmd_info: &mut CmdInfo, cmd_num: usize, cmd_idx: usize, pi
                 libc::signal(libc::SIGTTOU,libc::SIG_DFL);
                 libc::signal(libc::SIGTTIN,libc::SIG_DFL);
             }
-            // setup pgid
-            if cmd_idx == 0 {
-                setpgid(Pid::from_raw(0), getpid()).expect("Error setting pgid");       // setup new process group
-            } else {
-                setpgid(getpid(), Pid::from_raw(pgid)).expect("Error setting pgid");   // join process
-            }
-            // setup file descriptors
-            match &cmd_info.redir_to {      // check redir_to
-                Some(vec_redir_to) => {
-                    for redir_to in vec_redir_to {
-                        if redir_to.redir_type == ">&" {
-                            dup2(redir_to.fd_after, redir_to.fd_before).expect(dup_error);
-                        } else if redir_to.redir_type == ">" || redir_to.redir_type == ">>" {
-                            // try to open file
-                            let oflag;
-                            let fd_after;
-                            if redir_to.redir_type == ">" {
-                                oflag = OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC;
-                            } else {
-                                oflag = OFlag::O_APPEND | OFlag::O_CREAT | OFlag::O_WRONLY;
-                            }
-                            match open(redir_to.file_after.as_str(), oflag, Mode::S_IRWXU) {
-                                Ok(x) => fd_after = x,
-                                Err(e) => {
-                                    eprintln!("{}", e);
-                                    unsafe { libc::_exit(0) };
-                                }     // TODO: bash style error handling
-                            };
-                            // redirect
-                            dup2(fd_after, redir_to.fd_before).expect(dup_error);
-                            close(fd_after).expect(close_error);
-                        }
-                    }
-                },
-                None => {}
-            };
-            // TODO: redir_from
+            common::reset_sigpipe();
+            // setup pgid, non-interactive shells leave children in their own group
+            if sh.is_interactive {
+                if pgid == 0 {
+                    setpgid(Pid::from_raw(0), getpid()).ok();       // setup new process group
+                } else {
+                    setpgid(getpid(), Pid::from_raw(pgid)).ok();    // join process
+                }
+            }
+            // setup pipes first, redirections below take precedence over them
             for (i, pipe) in pipes.iter().enumerate() {     // close other pipes
                 if cmd_idx > 0 {
                     if i != cmd_idx-1 {
@@ -181,7 +655,34 @@ pub fn run_single_cmd(cmd_info: &mut CmdInfo, cmd_num: usize, cmd_idx: usize, pi
                 dup2(pipes[cmd_idx].1, 1).expect(dup_error);
                 close(pipes[cmd_idx].1).expect(close_error);
             }
-            
+            if let Err(e) = apply_redirects(&redirects, sh) {
+                eprintln!("mumsh: {}", e);
+                unsafe { libc::_exit(1) };
+            }
+            // prefix assignments go into the environment of the command
+            for (name, value) in cmd_info.assignments.iter().filter_map(|x| x.1.split_once('=')) {
+                sh.export_var(name, Some(value));
+            }
+            // builtins and functions in a pipeline
+            if cmd_info.compound.is_none() {
+                sh.is_interactive = false;
+                if let Some(x) = try_run_builtin(cmd_info, sh) {
+                    io::stdout().flush().ok();
+                    unsafe { libc::_exit(x) };
+                }
+            }
+            // compound commands run in the forked mumsh
+            if let Some(x) = &cmd_info.compound {
+                sh.is_interactive = false;
+                sh.loop_depth = 0;      // loops outside are not seen by break and continue
+                let status = run_compound(x, sh);
+                io::stdout().flush().ok();
+                unsafe { libc::_exit(status) };
+            }
+            // only redirections, eg: > file
+            if cmd_info.tokens.is_empty() {
+                unsafe { libc::_exit(0) };
+            }
             // setup execve arguments
             let c_file = CString::new(cmd_info.tokens[0].1.as_str()).expect(cstring_error);
             let c_arg: Vec<CString> = cmd_info.tokens
@@ -195,18 +696,21 @@ pub fn run_single_cmd(cmd_info: &mut CmdInfo, cmd_num: usize, cmd_idx: usize, pi
                     match e {
                         nix::Error::ENOEXEC => {
                             eprintln!("mumsh: exec format error: {}", cmd_info.tokens[0].1.as_str());
+                            126
                         }
                         nix::Error::ENOENT => {
                             eprintln!("mumsh: no such file or directory: {}", cmd_info.tokens[0].1.as_str());
+                            127
                         }
                         nix::Error::EACCES => {
                             eprintln!("mumsh: permission denied: {}", cmd_info.tokens[0].1.as_str());
+                            126
                         }
                         _ => {
                             eprintln!("mumsh: {}: {:?}", cmd_info.tokens[0].1.as_str(), e);
+                            126
                         }
-                    };
-                    e as i32
+                    }
                 },
             };
             unsafe { libc::_exit(exit_status) };
@@ -217,3 +721,152 @@ pub fn run_single_cmd(cmd_info: &mut CmdInfo, cmd_num: usize, cmd_idx: usize, pi
         }
     }
 }
+
+fn is_builtin_or_function(cmd_info: &CmdInfo, sh: &Mumsh) -> bool {
+    match cmd_info.tokens.first() {
+        Some(x) => cmd_info.compound.is_none() && (builtin::is_builtin(&x.1) || sh.functions.contains_key(&x.1)),
+        None => false,
+    }
+}
+
+/// run a builtin, a function or a compound command in mumsh herself
+/// the fds of mumsh are saved before the redirections and restored after,
+/// process substitutions stay open until it is done, returns None if a redirection fails
+fn run_in_shell<T>(redirects: &[Redir], sh: &mut Mumsh, run: impl FnOnce(&mut Mumsh) -> T) -> Option<T> {
+    // the commands run inside do not close or wait for them
+    let proc_subst_fds = std::mem::take(&mut sh.proc_subst_fds);
+    let proc_subst_pids = std::mem::take(&mut sh.proc_subst_pids);
+    // &> and >&file redirect stderr too
+    let mut fds: Vec<i32> = redirects.iter().filter(|x| x.fd_var.is_none()).map(|x| x.fd).collect();
+    if redirects.iter().any(|x| matches!(x.redir_type, RedirType::OutputAll | RedirType::AppendAll | RedirType::DupOutput)) {
+        fds.push(2);
+    }
+    fds.sort();
+    fds.dedup();
+    // the copies go above every fd redirected, so that no redirection overwrites them
+    let fd_min = fds.last().map_or(10, |x| (x + 1).max(10));
+    let saved: Vec<(i32, Option<i32>)> = fds.iter().map(|x| (*x, fcntl(*x, FcntlArg::F_DUPFD_CLOEXEC(fd_min)).ok())).collect();
+    let result = match apply_redirects(redirects, sh) {
+        Ok(_) => Some(run(sh)),
+        Err(e) => {
+            eprintln!("mumsh: {}", e);
+            None
+        }
+    };
+    io::stdout().flush().ok();
+    for (fd, fd_saved) in saved {
+        match fd_saved {
+            Some(x) => {
+                dup2(x, fd).ok();
+                close(x).ok();
+            },
+            None => {       // it was not open before
+                close(fd).ok();
+            }
+        };
+    }
+    sh.proc_subst_fds.extend(proc_subst_fds);
+    sh.proc_subst_pids.extend(proc_subst_pids);
+    result
+}
+
+/// set up the fds of a command, from left to right, eg: ">file 2>&1" sends both to file
+/// but "2>&1 >file" sends stderr where stdout was before
+fn apply_redirects(redirects: &[Redir], sh: &mut Mumsh) -> Result<(), String> {
+    for redirect in redirects {
+        let word = redirect.word.1.as_str();
+        let mut fd = redirect.fd;
+        if let Some(name) = &redirect.fd_var {
+            // {name}>&- closes the fd stored in name
+            if word == "-" && matches!(redirect.redir_type, RedirType::DupOutput | RedirType::DupInput) {
+                fd = match sh.get_var(name).and_then(|x| x.parse().ok()) {
+                    Some(x) => x,
+                    None => return Err(format!("{}: ambiguous redirect", name)),
+                };
+            }
+        }
+        let mut redir_type = redirect.redir_type;
+        let fd_new = match redir_type {
+            RedirType::DupOutput | RedirType::DupInput => {
+                if word == "-" {
+                    close(fd).ok();
+                    continue;
+                }
+                match word.parse::<i32>() {
+                    Ok(x) if fcntl(x, FcntlArg::F_GETFD).is_ok() => x,
+                    Ok(_) => return Err(format!("{}: Bad file descriptor", word)),
+                    // >&file is another way to write &>file
+                    Err(_) if redir_type == RedirType::DupOutput && !redirect.has_fd => {
+                        redir_type = RedirType::OutputAll;
+                        open_redirect_file(word, redir_type, sh)?
+                    },
+                    Err(_) => return Err(format!("{}: ambiguous redirect", word)),
+                }
+            },
+            RedirType::HereDoc => here_doc_fd(&redirect.here_doc).map_err(|e| format!("error creating here-document: {}", e.desc()))?,
+            RedirType::HereString => {
+                here_doc_fd(&format!("{}\n", word)).map_err(|e| format!("error creating here-document: {}", e.desc()))?
+            },
+            _ => open_redirect_file(word, redir_type, sh)?,
+        };
+        let is_dup = matches!(redir_type, RedirType::DupOutput | RedirType::DupInput);
+        if let Some(name) = &redirect.fd_var {
+            // {name}: the fd goes to a free one from 10 on
+            let fd_var = fcntl(fd_new, FcntlArg::F_DUPFD(10)).map_err(|e| format!("{}: {}", name, e.desc()))?;
+            if !is_dup {
+                close(fd_new).ok();
+            }
+            sh.set_var(name, &fd_var.to_string());
+            continue;
+        }
+        if fd_new != fd {
+            dup2(fd_new, fd).map_err(|e| format!("{}: {}", fd, e.desc()))?;
+            if !is_dup {
+                close(fd_new).ok();
+            }
+        }
+        if matches!(redir_type, RedirType::OutputAll | RedirType::AppendAll) {
+            dup2(fd, 2).map_err(|e| format!("2: {}", e.desc()))?;
+        }
+    }
+    Ok(())
+}
+
+/// open the file of a redirection, with noclobber > and &> do not truncate an existing regular file
+fn open_redirect_file(path: &str, redir_type: RedirType, sh: &Mumsh) -> Result<i32, String> {
+    let oflag = match redir_type {
+        RedirType::Input => OFlag::O_RDONLY,
+        RedirType::ReadWrite => OFlag::O_RDWR | OFlag::O_CREAT,
+        RedirType::Append | RedirType::AppendAll => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND,
+        _ => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC,
+    };
+    let mode = Mode::from_bits_truncate(0o666);
+    let no_clobber = sh.get_option("noclobber") && matches!(redir_type, RedirType::Output | RedirType::OutputAll);
+    let result = if no_clobber {
+        match open(path, OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL, mode) {
+            Err(nix::Error::EEXIST) => match stat(path) {
+                // devices like /dev/null may still be written to
+                Ok(x) if SFlag::from_bits_truncate(x.st_mode) & SFlag::S_IFMT != SFlag::S_IFREG => open(path, OFlag::O_WRONLY, mode),
+                _ => return Err(format!("{}: cannot overwrite existing file", path)),
+            },
+            x => x,
+        }
+    } else {
+        open(path, oflag, mode)
+    };
+    result.map_err(|e| format!("{}: {}", path, e.desc()))
+}
+
+/// write the content of a heredoc or here-string into an unlinked temporary file,
+/// returns its fd rewinded to the beginning
+fn here_doc_fd(content: &str) -> nix::Result<i32> {
+    let (fd, path) = mkstemp("/tmp/mumsh-heredoc-XXXXXX")?;
+    unlink(&path)?;
+    let mut written = 0;
+    let bytes = content.as_bytes();
+    while written < bytes.len() {
+        written += write(fd, &bytes[written..])?;
+    }
+    lseek(fd, 0, Whence::SeekSet)?;
+    Ok(fd)
+}
@@ -0,0 +1,459 @@
+use crate::mumsh::Mumsh;
+
+/// deepest nesting of variables that refer to other expressions, eg: a=b b=a
+const MAX_RECURSION: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArithToken {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+    LeftParen,
+    RightParen
+}
+
+/// operators, longest first so that the tokenizer is greedy
+const OPERATORS: [&str; 37] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=",
+    "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "?", ":", "=", ","
+];
+
+#[derive(Debug, Clone)]
+enum ArithExpr {
+    Number(i64),
+    Var(String),
+    Unary(&'static str, Box<ArithExpr>),
+    Binary(&'static str, Box<ArithExpr>, Box<ArithExpr>),
+    Assign(&'static str, String, Box<ArithExpr>),       // = += ... with the variable name
+    IncDec(&'static str, bool, String),                 // ++ or --, true if prefix
+    Ternary(Box<ArithExpr>, Box<ArithExpr>, Box<ArithExpr>)
+}
+
+/// evaluate an arithmetic expression, as in $(( )) and (( ))
+/// eg: 1 + 2 * 3 => 7, x += 2 => value of x after the assignment
+pub fn eval(expr: &str, sh: &mut Mumsh) -> Result<i64, String> {
+    eval_depth(expr, sh, 0)
+}
+
+fn eval_depth(expr: &str, sh: &mut Mumsh, depth: usize) -> Result<i64, String> {
+    if depth > MAX_RECURSION {
+        return Err(format!("{}: expression recursion level exceeded", expr.trim()));
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = ArithParser { tokens: &tokens, pos: 0 };
+    let ast = parser.parse_comma().map_err(|e| format!("{}: {}", expr.trim(), e))?;
+    if parser.pos < tokens.len() {
+        return Err(format!("{}: syntax error in expression (error token is \"{}\")",
+                           expr.trim(), token_to_string(&tokens[parser.pos])));
+    }
+    let mut evaluator = ArithEvaluator { sh, depth };
+    if depth > 0 {
+        return evaluator.eval(&ast);
+    }
+    evaluator.eval(&ast).map_err(|e| format!("{}: {}", expr.trim(), e))
+}
+
+fn token_to_string(token: &ArithToken) -> String {
+    match token {
+        ArithToken::Number(x) => x.to_string(),
+        ArithToken::Name(x) => x.clone(),
+        ArithToken::Op(x) => x.to_string(),
+        ArithToken::LeftParen => String::from("("),
+        ArithToken::RightParen => String::from(")"),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<ArithToken>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_alphanumeric() || c == '_' {
+            // numbers may be written as base#digits, where @ and _ are digits too
+            let is_number = c.is_ascii_digit();
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_'
+                                      || (is_number && (chars[i] == '#' || chars[i] == '@'))) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if is_number {
+                tokens.push(ArithToken::Number(parse_number(&word, expr)?));
+            } else {
+                tokens.push(ArithToken::Name(word));
+            }
+            continue;
+        }
+        if c == '(' {
+            tokens.push(ArithToken::LeftParen);
+            i += 1;
+            continue;
+        }
+        if c == ')' {
+            tokens.push(ArithToken::RightParen);
+            i += 1;
+            continue;
+        }
+        let rest: String = chars[i..chars.len().min(i+3)].iter().collect();
+        match OPERATORS.iter().find(|x| rest.starts_with(**x)) {
+            Some(x) => {
+                tokens.push(ArithToken::Op(x));
+                i += x.len();
+            },
+            None => return Err(format!("{}: syntax error: operand expected (error token is \"{}\")",
+                                       expr.trim(), chars[i..].iter().collect::<String>())),
+        };
+    }
+    Ok(tokens)
+}
+
+/// parse an integer constant: 10, 0x1f, 017 and base#digits like 2#101
+fn parse_number(word: &str, expr: &str) -> Result<i64, String> {
+    let invalid = || format!("{}: value too great for base (error token is \"{}\")", expr.trim(), word);
+    let (base, digits) = if let Some((base, digits)) = word.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(x) if (2..=64).contains(&x) => (x, digits),
+            _ => return Err(format!("{}: invalid arithmetic base (error token is \"{}\")", expr.trim(), word)),
+        }
+    } else if let Some(x) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        (16, x)
+    } else if word.len() > 1 && word.starts_with('0') {
+        (8, &word[1..])
+    } else {
+        (10, word)
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // digits beyond 9 are a-z, A-Z, @ and _, letters are case insensitive up to base 36
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+/// recursive descent parser, one function per precedence level
+struct ArithParser<'a> {
+    tokens: &'a [ArithToken],
+    pos: usize
+}
+
+impl ArithParser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(ArithToken::Op(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn error(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some(x) => format!("syntax error in expression (error token is \"{}\")", token_to_string(x)),
+            None => String::from("syntax error: operand expected"),
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<ArithExpr, String> {
+        let mut left = self.parse_assign()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            let right = self.parse_assign()?;
+            left = ArithExpr::Binary(",", Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_assign(&mut self) -> Result<ArithExpr, String> {
+        // name followed by an assignment operator
+        if let (Some(ArithToken::Name(name)), Some(ArithToken::Op(op))) = (self.tokens.get(self.pos), self.tokens.get(self.pos+1)) {
+            if ["=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|="].contains(op) {
+                self.pos += 2;
+                let value = self.parse_assign()?;
+                return Ok(ArithExpr::Assign(op, name.clone(), Box::new(value)));
+            }
+        }
+        self.parse_ternary()
+    }
+
+    fn parse_ternary(&mut self) -> Result<ArithExpr, String> {
+        let condition = self.parse_binary(0)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let if_true = self.parse_assign()?;
+        if self.peek_op() != Some(":") {
+            return Err(String::from("`:' expected for conditional expression"));
+        }
+        self.pos += 1;
+        let if_false = self.parse_assign()?;
+        Ok(ArithExpr::Ternary(Box::new(condition), Box::new(if_true), Box::new(if_false)))
+    }
+
+    /// binary operators from the loosest to the tightest, ** is handled apart since it is right associative
+    fn parse_binary(&mut self, level: usize) -> Result<ArithExpr, String> {
+        const LEVELS: [&[&str]; 10] = [
+            &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<=", ">=", "<", ">"],
+            &["<<", ">>"], &["+", "-"], &["*", "/", "%"]
+        ];
+        if level == LEVELS.len() {
+            return self.parse_power();
+        }
+        let mut left = self.parse_binary(level + 1)?;
+        while let Some(op) = self.peek_op() {
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let right = self.parse_binary(level + 1)?;
+            left = ArithExpr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_power(&mut self) -> Result<ArithExpr, String> {
+        let base = self.parse_unary()?;
+        if self.peek_op() == Some("**") {
+            self.pos += 1;
+            let exponent = self.parse_power()?;
+            return Ok(ArithExpr::Binary("**", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_unary(&mut self) -> Result<ArithExpr, String> {
+        match self.peek_op() {
+            Some(op) if op == "++" || op == "--" => {
+                self.pos += 1;
+                match self.tokens.get(self.pos) {
+                    Some(ArithToken::Name(name)) => {
+                        self.pos += 1;
+                        Ok(ArithExpr::IncDec(op, true, name.clone()))
+                    },
+                    _ => {
+                        // --5 is the same as -(-5)
+                        let operand = self.parse_unary()?;
+                        let inner = ArithExpr::Unary(&op[..1], Box::new(operand));
+                        Ok(ArithExpr::Unary(&op[..1], Box::new(inner)))
+                    }
+                }
+            },
+            Some(op) if op == "-" || op == "+" || op == "!" || op == "~" => {
+                self.pos += 1;
+                let operand = self.parse_unary()?;
+                Ok(ArithExpr::Unary(op, Box::new(operand)))
+            },
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<ArithExpr, String> {
+        match self.tokens.get(self.pos) {
+            Some(ArithToken::Number(x)) => {
+                self.pos += 1;
+                Ok(ArithExpr::Number(*x))
+            },
+            Some(ArithToken::Name(name)) => {
+                self.pos += 1;
+                if let Some(op) = self.peek_op() {
+                    if op == "++" || op == "--" {
+                        self.pos += 1;
+                        return Ok(ArithExpr::IncDec(op, false, name.clone()));
+                    }
+                }
+                Ok(ArithExpr::Var(name.clone()))
+            },
+            Some(ArithToken::LeftParen) => {
+                self.pos += 1;
+                let inner = self.parse_comma()?;
+                if self.tokens.get(self.pos) != Some(&ArithToken::RightParen) {
+                    return Err(String::from("missing `)'"));
+                }
+                self.pos += 1;
+                Ok(inner)
+            },
+            _ => Err(self.error()),
+        }
+    }
+}
+
+struct ArithEvaluator<'a> {
+    sh: &'a mut Mumsh,
+    depth: usize
+}
+
+impl ArithEvaluator<'_> {
+    fn eval(&mut self, expr: &ArithExpr) -> Result<i64, String> {
+        match expr {
+            ArithExpr::Number(x) => Ok(*x),
+            ArithExpr::Var(name) => self.get(name),
+            ArithExpr::Unary(op, operand) => {
+                let x = self.eval(operand)?;
+                Ok(match *op {
+                    "-" => x.wrapping_neg(),
+                    "!" => (x == 0) as i64,
+                    "~" => !x,
+                    _ => x,
+                })
+            },
+            ArithExpr::Binary(op, left, right) => {
+                // && and || do not evaluate the right side if not needed
+                let x = self.eval(left)?;
+                if *op == "&&" && x == 0 {
+                    return Ok(0);
+                }
+                if *op == "||" && x != 0 {
+                    return Ok(1);
+                }
+                let y = self.eval(right)?;
+                apply_binary(op, x, y)
+            },
+            ArithExpr::Assign(op, name, value) => {
+                let y = self.eval(value)?;
+                let result = if *op == "=" {
+                    y
+                } else {
+                    let x = self.get(name)?;
+                    apply_binary(&op[..op.len()-1], x, y)?
+                };
+                self.sh.set_var(name, &result.to_string());
+                Ok(result)
+            },
+            ArithExpr::IncDec(op, prefix, name) => {
+                let x = self.get(name)?;
+                let result = if *op == "++" { x.wrapping_add(1) } else { x.wrapping_sub(1) };
+                self.sh.set_var(name, &result.to_string());
+                Ok(if *prefix { result } else { x })
+            },
+            ArithExpr::Ternary(condition, if_true, if_false) => {
+                if self.eval(condition)? != 0 {
+                    self.eval(if_true)
+                } else {
+                    self.eval(if_false)
+                }
+            },
+        }
+    }
+
+    /// value of a variable, its content is itself evaluated as an expression
+    fn get(&mut self, name: &str) -> Result<i64, String> {
+        let value = match self.sh.get_var(name) {
+            Some(x) => x,
+            None => return Ok(0),
+        };
+        if let Ok(x) = value.trim().parse::<i64>() {
+            return Ok(x);
+        }
+        eval_depth(&value, self.sh, self.depth + 1)
+    }
+}
+
+fn apply_binary(op: &str, x: i64, y: i64) -> Result<i64, String> {
+    Ok(match op {
+        "+" => x.wrapping_add(y),
+        "-" => x.wrapping_sub(y),
+        "*" => x.wrapping_mul(y),
+        "/" | "%" => {
+            if y == 0 {
+                return Err(String::from("division by 0"));
+            }
+            if op == "/" { x.wrapping_div(y) } else { x.wrapping_rem(y) }
+        },
+        "**" => {
+            if y < 0 {
+                return Err(String::from("exponent less than 0"));
+            }
+            x.wrapping_pow(y.min(u32::MAX as i64) as u32)
+        },
+        "<<" => x.wrapping_shl(y as u32),
+        ">>" => x.wrapping_shr(y as u32),
+        "<" => (x < y) as i64,
+        ">" => (x > y) as i64,
+        "<=" => (x <= y) as i64,
+        ">=" => (x >= y) as i64,
+        "==" => (x == y) as i64,
+        "!=" => (x != y) as i64,
+        "&" => x & y,
+        "^" => x ^ y,
+        "|" => x | y,
+        "&&" => (x != 0 && y != 0) as i64,
+        "||" => (x != 0 || y != 0) as i64,
+        "," => y,
+        _ => return Err(format!("{}: unknown operator", op)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_str(expr: &str) -> Result<i64, String> {
+        eval(expr, &mut Mumsh::new())
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval_str("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval_str("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval_str("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval_str("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(eval_str("-2 ** 2"), Ok(4));
+        assert_eq!(eval_str("1 << 2 + 1"), Ok(8));
+        assert_eq!(eval_str("1 + 2 == 3 && 4 < 5"), Ok(1));
+        assert_eq!(eval_str("0 ? 2 : 1 ? 3 : 4"), Ok(3));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(eval_str("0x10 + 010 + 2#101"), Ok(29));
+        assert_eq!(eval_str(""), Ok(0));
+    }
+
+    #[test]
+    fn overflow_wraps() {
+        assert_eq!(eval_str("9223372036854775807 + 1"), Ok(i64::MIN));
+        assert_eq!(eval_str("-9223372036854775807 - 2"), Ok(i64::MAX));
+        assert_eq!(eval_str("2 ** 64"), Ok(0));
+        assert_eq!(eval_str("-9223372036854775808 / -1"), Ok(i64::MIN));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(eval_str("5 / 0"), Err(String::from("5 / 0: division by 0")));
+        assert_eq!(eval_str("5 % (2 - 2)"), Err(String::from("5 % (2 - 2): division by 0")));
+        // the right side of && is not evaluated when the left is 0
+        assert_eq!(eval_str("0 && 1 / 0"), Ok(0));
+    }
+
+    #[test]
+    fn assignments() {
+        let mut sh = Mumsh::new();
+        assert_eq!(eval("arith_x = 3, arith_x *= 2, arith_x++", &mut sh), Ok(6));
+        assert_eq!(sh.get_var("arith_x"), Some(String::from("7")));
+        // a variable holding an expression is evaluated too
+        sh.set_var("arith_y", "arith_x + 1");
+        assert_eq!(eval("arith_y * 2", &mut sh), Ok(16));
+    }
+}
@@ -0,0 +1,153 @@
+use super::next_index;
+
+/// brace expansion on an unquoted word, done before any other expansion
+/// eg: "a{b,c}d" => ["abd", "acd"], "{1..5..2}" => ["1", "3", "5"]
+///     "{01..03}" => ["01", "02", "03"], "{a}" => ["{a}"]
+pub fn expand(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '{' {
+            i = next_index(&chars, i);
+            continue;
+        }
+        if let Some((end, commas)) = find_brace_end(&chars, i) {
+            let alternatives = if commas.is_empty() {
+                let inner: String = chars[i+1..end].iter().collect();
+                sequence(&inner)
+            } else {
+                let mut parts = Vec::new();
+                let mut start = i + 1;
+                for comma in commas.iter().chain([end].iter()) {
+                    parts.push(chars[start..*comma].iter().collect::<String>());
+                    start = comma + 1;
+                }
+                Some(parts)
+            };
+            if let Some(alternatives) = alternatives {
+                let preamble: String = chars[..i].iter().collect();
+                let postscript: String = chars[end+1..].iter().collect();
+                let tails = expand(&postscript);
+                let mut result = Vec::new();
+                for alternative in alternatives {
+                    for word in expand(&alternative) {
+                        for tail in tails.iter() {
+                            result.push(format!("{}{}{}", preamble, word, tail));
+                        }
+                    }
+                }
+                return result;
+            }
+        }
+        // not a valid brace expression, look for one further in
+        i += 1;
+    }
+    vec![text.to_string()]
+}
+
+/// find the } matching the { at start, with the positions of its top level commas
+fn find_brace_end(chars: &[char], start: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((i, commas));
+                }
+            },
+            ',' if depth == 1 => commas.push(i),
+            _ => {},
+        };
+        i = next_index(chars, i);
+    }
+    None
+}
+
+/// expand a sequence expression x..y[..step], None if it is not one
+/// eg: "1..3" => ["1", "2", "3"], "c..a" => ["c", "b", "a"]
+fn sequence(inner: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = inner.split("..").collect();
+    if parts.len() != 2 && parts.len() != 3 {
+        return None;
+    }
+    let step = match parts.get(2) {
+        Some(x) => x.parse::<i64>().ok()?.checked_abs()?.max(1),
+        None => 1,
+    };
+    if let (Ok(first), Ok(last)) = (parts[0].parse::<i64>(), parts[1].parse::<i64>()) {
+        let padded = [parts[0], parts[1]].iter().any(|x| {
+            let digits = x.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        });
+        let width = if padded { parts[0].len().max(parts[1].len()) } else { 0 };
+        return Some(range(first, last, step)?.map(|x| format!("{:0width$}", x, width = width)).collect());
+    }
+    let mut first = parts[0].chars();
+    let mut last = parts[1].chars();
+    match (first.next(), first.next(), last.next(), last.next()) {
+        (Some(a), None, Some(b), None) if a.is_ascii() && b.is_ascii() => {
+            Some(range(a as i64, b as i64, step)?.map(|x| (x as u8 as char).to_string()).collect())
+        },
+        _ => None,
+    }
+}
+
+/// the numbers from first to last going by step, downwards if last < first,
+/// None if the distance between them does not fit in an i64
+fn range(first: i64, last: i64, step: i64) -> Option<impl Iterator<Item = i64>> {
+    let count = (first.checked_sub(last)?.checked_abs()? / step).checked_add(1)?;
+    let step = if last < first { -step } else { step };
+    Some((0..count).map(move |x| first + x * step))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(expand("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("x{a,{b,c}}"), ["xa", "xb", "xc"]);
+        assert_eq!(expand("{a,}b"), ["ab", "b"]);
+        assert_eq!(expand("{a}"), ["{a}"]);
+        assert_eq!(expand("a{b"), ["a{b"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(expand("{1..3}"), ["1", "2", "3"]);
+        assert_eq!(expand("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(expand("{01..03}"), ["01", "02", "03"]);
+        assert_eq!(expand("{c..a}"), ["c", "b", "a"]);
+        assert_eq!(expand("{a..bc}"), ["{a..bc}"]);
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(expand("{1..10..3}"), ["1", "4", "7", "10"]);
+        assert_eq!(expand("{10..1..-4}"), ["10", "6", "2"]);
+        assert_eq!(expand("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(expand("{1..2..0}"), ["1", "2"]);
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(expand("{a,b{1..2}}"), ["a", "b1", "b2"]);
+        assert_eq!(expand("{{1..2},x}y"), ["1y", "2y", "xy"]);
+    }
+
+    #[test]
+    fn overflow() {
+        // ranges too long to count are left as they are
+        assert_eq!(expand("{-9223372036854775807..9223372036854775807}"), ["{-9223372036854775807..9223372036854775807}"]);
+        assert_eq!(expand("{0..9223372036854775807}"), ["{0..9223372036854775807}"]);
+        assert_eq!(expand("{1..2..-9223372036854775808}"), ["{1..2..-9223372036854775808}"]);
+        assert_eq!(expand("{9223372036854775806..9223372036854775807}"), ["9223372036854775806", "9223372036854775807"]);
+    }
+}
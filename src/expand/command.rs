@@ -0,0 +1,135 @@
+use std::io::{self, Write};
+
+use nix::unistd::{close, dup2, fork, pipe, read, setpgid, ForkResult, Pid};
+use nix::sys::wait::waitpid;
+
+use crate::{common, executor, mumsh::Mumsh};
+
+/// run cmd in a forked subshell and capture what it writes to stdout
+/// trailing newlines are removed, eg: $(echo hi) => "hi"
+pub fn substitute(cmd: &str, sh: &mut Mumsh) -> Result<String, String> {
+    let (fd_read, fd_write) = match pipe() {
+        Ok(x) => x,
+        Err(e) => return Err(format!("pipe error {}", e)),
+    };
+    io::stdout().flush().ok();
+    match unsafe{fork()} {
+        Ok(ForkResult::Child) => {
+            close(fd_read).ok();
+            dup2(fd_write, 1).expect("mumsh: error duplicating file descriptor");
+            close(fd_write).ok();
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+            }
+            common::reset_sigpipe();
+            // the subshell never does job control
+            sh.is_interactive = false;
+            for fd in std::mem::take(&mut sh.proc_subst_fds) {
+                close(fd).ok();
+            }
+            sh.proc_subst_pids.clear();
+            let status = executor::run_script(cmd, sh);
+            io::stdout().flush().ok();
+            unsafe { libc::_exit(status) };
+        },
+        Ok(ForkResult::Parent { child }) => {
+            close(fd_write).ok();
+            let mut output = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                match read(fd_read, &mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => output.extend_from_slice(&buf[..n]),
+                    Err(nix::Error::EINTR) => continue,
+                    Err(_) => break,
+                };
+            }
+            close(fd_read).ok();
+            let status = loop {
+                match waitpid(child, None) {
+                    Ok(x) => break common::wait_status_to_code(&x),
+                    Err(nix::Error::EINTR) => continue,
+                    Err(_) => break 1,
+                }
+            };
+            sh.last_status = status;
+            sh.subst_status = Some(status);
+            let mut output = String::from_utf8_lossy(&output).to_string();
+            while output.ends_with('\n') {
+                output.pop();
+            }
+            Ok(output)
+        },
+        Err(e) => Err(format!("fork failed: {}", e)),
+    }
+}
+
+/// run cmd in a forked subshell connected to a pipe, returns the path to the other end
+/// with is_input cmd writes to the pipe, eg: <(ls) => "/dev/fd/63", otherwise cmd reads from it
+/// the fd stays open and the subshell unwaited until the executor is done with the command
+pub fn process_substitute(cmd: &str, is_input: bool, sh: &mut Mumsh) -> Result<String, String> {
+    let (fd_read, fd_write) = match pipe() {
+        Ok(x) => x,
+        Err(e) => return Err(format!("pipe error {}", e)),
+    };
+    let (fd_mine, fd_child, fd_std) = if is_input { (fd_read, fd_write, 1) } else { (fd_write, fd_read, 0) };
+    // with job control all subshells of a command share a group, the one its job joins
+    let pgid = sh.proc_subst_pids.first().map_or(Pid::from_raw(0), |x| Pid::from_raw(*x));
+    io::stdout().flush().ok();
+    match unsafe{fork()} {
+        Ok(ForkResult::Child) => {
+            if sh.is_interactive {
+                setpgid(Pid::from_raw(0), pgid).ok();
+            }
+            close(fd_mine).ok();
+            // the pipes of earlier process substitutions are not for this one
+            for fd in std::mem::take(&mut sh.proc_subst_fds) {
+                close(fd).ok();
+            }
+            sh.proc_subst_pids.clear();
+            dup2(fd_child, fd_std).expect("mumsh: error duplicating file descriptor");
+            close(fd_child).ok();
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+                libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+            }
+            common::reset_sigpipe();
+            sh.is_interactive = false;
+            let status = executor::run_script(cmd, sh);
+            io::stdout().flush().ok();
+            unsafe { libc::_exit(status) };
+        },
+        Ok(ForkResult::Parent { child }) => {
+            if sh.is_interactive {
+                setpgid(child, if pgid.as_raw() == 0 { child } else { pgid }).ok();
+            }
+            close(fd_child).ok();
+            sh.proc_subst_fds.push(fd_mine);
+            sh.proc_subst_pids.push(child.as_raw());
+            Ok(format!("/dev/fd/{}", fd_mine))
+        },
+        Err(e) => Err(format!("fork failed: {}", e)),
+    }
+}
+
+/// undo the backslash escapes that are special inside backticks: \$ \` \\
+/// eg: `echo \$HOME` => echo $HOME
+pub fn unescape_backticks(cmd: &str) -> String {
+    let mut result = String::new();
+    let mut chars = cmd.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(x) = chars.peek() {
+                if *x == '$' || *x == '`' || *x == '\\' {
+                    result.push(*x);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        result.push(c);
+    }
+    result
+}
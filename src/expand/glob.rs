@@ -0,0 +1,136 @@
+use std::fs;
+
+use crate::mumsh::Mumsh;
+use crate::expand::pattern::Pattern;
+
+/// expand a pathname pattern against the filesystem, the result is sorted
+/// hidden files only match a pattern starting with a dot, unless dotglob is set
+/// ** matches any number of directories when globstar is set
+/// eg: src/*.rs => ["src/common.rs", "src/executor.rs", ...]
+pub fn glob(pattern: &str, sh: &Mumsh) -> Vec<String> {
+    let dotglob = sh.get_option("dotglob");
+    let globstar = sh.get_option("globstar");
+    let components: Vec<&str> = pattern.split('/').collect();
+    let mut results = Vec::new();
+    if pattern.starts_with('/') {
+        glob_dir("/", &components[1..], dotglob, globstar, &mut results);
+    } else {
+        glob_dir("", &components, dotglob, globstar, &mut results);
+    }
+    results.sort();
+    results.dedup();
+    results
+}
+
+/// check whether an unquoted part of a word has a glob character
+pub fn has_glob_chars(pattern: &str) -> bool {
+    !Pattern::new(pattern).is_literal()
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        return name.to_string();
+    }
+    if prefix.ends_with('/') {
+        return format!("{}{}", prefix, name);
+    }
+    format!("{}/{}", prefix, name)
+}
+
+fn is_dir(path: &str) -> bool {
+    let path = if path.is_empty() { "." } else { path };
+    fs::metadata(path).map(|x| x.is_dir()).unwrap_or(false)
+}
+
+/// names in a directory, not sorted, . and .. excluded
+fn read_dir(path: &str) -> Vec<String> {
+    let path = if path.is_empty() { "." } else { path };
+    match fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|x| x.ok())
+                              .map(|x| x.file_name().to_string_lossy().to_string())
+                              .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// match the remaining components inside the directory prefix
+fn glob_dir(prefix: &str, components: &[&str], dotglob: bool, globstar: bool, results: &mut Vec<String>) {
+    let component = match components.first() {
+        Some(x) => *x,
+        None => return,
+    };
+    let rest = &components[1..];
+    // a trailing / only matches directories, a // in the middle is the same as /
+    if component.is_empty() {
+        if rest.is_empty() {
+            if is_dir(prefix) {
+                results.push(format!("{}/", prefix));
+            }
+        } else {
+            glob_dir(prefix, rest, dotglob, globstar, results);
+        }
+        return;
+    }
+    if component == "**" && globstar {
+        glob_star(prefix, rest, dotglob, results);
+        return;
+    }
+    let pattern = Pattern::new(component);
+    if pattern.is_literal() {
+        let path = join(prefix, &pattern.literal());
+        if rest.is_empty() {
+            if fs::symlink_metadata(&path).is_ok() {
+                results.push(path);
+            }
+        } else if is_dir(&path) {
+            glob_dir(&path, rest, dotglob, globstar, results);
+        }
+        return;
+    }
+    for name in read_dir(prefix) {
+        if name.starts_with('.') && !dotglob && !pattern.starts_with_dot() {
+            continue;
+        }
+        if !pattern.matches(&name) {
+            continue;
+        }
+        let path = join(prefix, &name);
+        if rest.is_empty() {
+            results.push(path);
+        } else if is_dir(&path) {
+            glob_dir(&path, rest, dotglob, globstar, results);
+        }
+    }
+}
+
+/// ** matches zero or more directories, alone at the end it matches everything below prefix
+fn glob_star(prefix: &str, rest: &[&str], dotglob: bool, results: &mut Vec<String>) {
+    if rest.is_empty() {
+        for name in read_dir(prefix) {
+            if name.starts_with('.') && !dotglob {
+                continue;
+            }
+            let path = join(prefix, &name);
+            results.push(path.clone());
+            if is_real_dir(&path) {
+                glob_star(&path, rest, dotglob, results);
+            }
+        }
+        return;
+    }
+    glob_dir(prefix, rest, dotglob, true, results);
+    for name in read_dir(prefix) {
+        if name.starts_with('.') && !dotglob {
+            continue;
+        }
+        let path = join(prefix, &name);
+        if is_real_dir(&path) {
+            glob_star(&path, rest, dotglob, results);
+        }
+    }
+}
+
+/// directories that are not symlinks, ** does not follow symlinks to avoid loops
+fn is_real_dir(path: &str) -> bool {
+    fs::symlink_metadata(path).map(|x| x.is_dir()).unwrap_or(false)
+}
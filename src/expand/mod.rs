@@ -0,0 +1,549 @@
+pub mod arith;
+pub mod brace;
+pub mod command;
+pub mod glob;
+pub mod param;
+pub mod pattern;
+pub mod tilde;
+
+use crate::mumsh::Mumsh;
+use crate::parser::lexer::{find_command_subst_end, read_ansi_c};
+use crate::types::Token;
+
+use param::ParamValue;
+
+/// where a character of an expanded word comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharKind {
+    Quoted,     // inside quotes, never split
+    Literal,    // typed by the user without quotes
+    Expanded    // unquoted result of an expansion, subject to field splitting
+}
+
+/// a word in the middle of expansion
+#[derive(Debug, Clone, Default)]
+pub struct Field {
+    pub chars: Vec<(char, CharKind)>,
+    pub quoted: bool                    // has a quoted part, so it is kept even if empty
+}
+
+impl Field {
+    fn new(quoted: bool) -> Field {
+        Field { chars: Vec::new(), quoted }
+    }
+
+    pub fn push_str(&mut self, string: &str, kind: CharKind) {
+        self.chars.extend(string.chars().map(|x| (x, kind)));
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().map(|x| x.0).collect()
+    }
+
+    /// the field as a pattern, quoted characters lose their special meaning
+    pub fn pattern(&self) -> String {
+        let mut result = String::new();
+        for (c, kind) in self.chars.iter() {
+            if *kind == CharKind::Quoted && "*?[]\\".contains(*c) {
+                result.push('\\');
+            }
+            result.push(*c);
+        }
+        result
+    }
+}
+
+/// expand tokens into the final words, with brace expansion, field splitting and globbing
+/// eg: [("", "echo"), ("", "$A"), ("\"", "$A")] where A="1 2"
+///     => ["echo", "1", "2", "1 2"]
+pub fn expand_tokens(tokens: &[Token], sh: &mut Mumsh) -> Result<Vec<Token>, String> {
+    let mut words = Vec::new();
+    for token in tokens {
+        if token.0.is_empty() {
+            for text in brace::expand(&token.1) {
+                words.push((String::new(), text));
+            }
+        } else {
+            words.push(token.clone());
+        }
+    }
+    let mut result = Vec::new();
+    for token in words.iter() {
+        let fields = expand_token(token, sh)?;
+        for field in split_fields(fields, sh) {
+            if field.chars.is_empty() && !field.quoted {
+                continue;
+            }
+            let pattern = field.pattern();
+            if !glob::has_glob_chars(&pattern) {
+                result.push((token.0.clone(), field.text()));
+                continue;
+            }
+            let paths = glob::glob(&pattern, sh);
+            if paths.is_empty() {
+                if sh.get_option("nullglob") {
+                    continue;
+                }
+                if sh.get_option("failglob") {
+                    return Err(format!("no match: {}", field.text()));
+                }
+                result.push((token.0.clone(), field.text()));
+                continue;
+            }
+            for path in paths {
+                result.push((token.0.clone(), path));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// expand a token into exactly one word, without field splitting
+/// used for assignments, redirection targets and here-strings
+pub fn expand_token_string(token: &Token, sh: &mut Mumsh) -> Result<String, String> {
+    let fields = expand_token(token, sh)?;
+    Ok(join_fields(&fields))
+}
+
+/// expand a token into a pattern, without field splitting or globbing
+/// quoted characters are escaped, eg: "*"x* => \*x*
+pub fn expand_pattern(token: &Token, sh: &mut Mumsh) -> Result<String, String> {
+    let fields = expand_token(token, sh)?;
+    let patterns: Vec<String> = fields.iter().map(|x| x.pattern()).collect();
+    Ok(patterns.join(" "))
+}
+
+/// expand the body of a heredoc whose delimiter is not quoted
+pub fn expand_here_doc(body: &str, sh: &mut Mumsh) -> Result<String, String> {
+    // \" is no escape in heredocs, its backslash stays
+    let fields = expand_text(&body.replace("\\\"", "\\\\\""), true, false, sh)?;
+    Ok(join_fields(&fields))
+}
+
+/// join fields with spaces, where only one word is allowed
+pub fn join_fields(fields: &[Field]) -> String {
+    let words: Vec<String> = fields.iter().map(|x| x.text()).collect();
+    words.join(" ")
+}
+
+/// expand a single token according to its quote kind
+fn expand_token(token: &Token, sh: &mut Mumsh) -> Result<Vec<Field>, String> {
+    match token.0.as_str() {
+        "\'" => {
+            let mut field = Field::new(true);
+            field.push_str(&token.1, CharKind::Quoted);
+            Ok(vec![field])
+        },
+        "\"" => expand_text(&token.1, true, false, sh),
+        _ => match token.1.split_once('=') {
+            // words that look like assignments get tildes expanded in the value too
+            Some((name, value)) if param::is_name(name) => {
+                let mut fields = vec![Field::new(false)];
+                fields[0].push_str(&format!("{}=", name), CharKind::Literal);
+                append_fields(&mut fields, expand_word(value, true, sh)?);
+                Ok(fields)
+            },
+            _ => expand_word(&token.1, false, sh),
+        },
+    }
+}
+
+/// expand the value of an assignment into one word, eg: PATH=~/bin:$PATH
+pub fn expand_assignment(value: &Token, sh: &mut Mumsh) -> Result<String, String> {
+    if !value.0.is_empty() {
+        return expand_token_string(value, sh);
+    }
+    let fields = expand_word(&value.1, true, sh)?;
+    Ok(join_fields(&fields))
+}
+
+/// expand an unquoted word, with the tilde prefix at its start
+/// in assignment values, tilde prefixes right after each : are expanded as well
+fn expand_word(text: &str, assignment: bool, sh: &mut Mumsh) -> Result<Vec<Field>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut prefixes = vec![0];
+    if assignment {
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == ':' {
+                prefixes.push(i + 1);
+            }
+            i = next_index(&chars, i);
+        }
+    }
+    let mut fields = vec![Field::new(false)];
+    let mut start = 0;
+    for prefix in prefixes {
+        if prefix < start || chars.get(prefix) != Some(&'~') {
+            continue;
+        }
+        let rest: String = chars[prefix..].iter().collect();
+        if let Some((dir, len)) = tilde::expand_prefix(&rest, sh) {
+            let before: String = chars[start..prefix].iter().collect();
+            append_fields(&mut fields, expand_text(&before, false, true, sh)?);
+            // the result is not split or globbed any further
+            fields.last_mut().unwrap().push_str(&dir, CharKind::Quoted);
+            start = prefix + len;
+        }
+    }
+    let rest: String = chars[start..].iter().collect();
+    append_fields(&mut fields, expand_text(&rest, false, true, sh)?);
+    Ok(fields)
+}
+
+/// expand $ inside text, quoted text is expanded as if in double quotes
+/// with process_quotes, quotes inside text are removed and what they enclose is quoted,
+/// words made of several parts come here as the text Word::raw gives, see Word::to_token
+/// a backslash quotes the next character, in double quotes only $ ` " \\ and newline
+/// "$@" is the only expansion that produces more than one field when quoted
+pub fn expand_text(text: &str, quoted: bool, process_quotes: bool, sh: &mut Mumsh) -> Result<Vec<Field>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let literal_kind = if quoted { CharKind::Quoted } else { CharKind::Literal };
+    let expanded_kind = if quoted { CharKind::Quoted } else { CharKind::Expanded };
+    let mut fields = vec![Field::new(quoted)];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' {
+            match chars.get(i+1) {
+                Some('\n') => i += 2,      // line continuation
+                Some(&x) if !quoted || "$`\"\\\n".contains(x) => {
+                    fields.last_mut().unwrap().chars.push((x, CharKind::Quoted));
+                    i += 2;
+                },
+                _ => {
+                    fields.last_mut().unwrap().chars.push((c, literal_kind));
+                    i += 1;
+                }
+            };
+            continue;
+        }
+        if process_quotes && !quoted && c == '$' && chars.get(i+1) == Some(&'\'') {
+            let (string, end) = read_ansi_c(&chars, i+2).unwrap_or_else(|| (chars[i+2..].iter().collect(), chars.len()));
+            let field = fields.last_mut().unwrap();
+            field.push_str(&string, CharKind::Quoted);
+            field.quoted = true;
+            i = end + 1;
+            continue;
+        }
+        if process_quotes && c == '\"' {
+            if quoted {         // already quoted, the quote is simply removed
+                i += 1;
+                continue;
+            }
+            let end = find_quote_end(&chars, i+1);
+            let inner: String = chars[i+1..end].iter().collect();
+            let inner_fields = expand_text(&inner, true, false, sh)?;
+            append_fields(&mut fields, inner_fields);
+            fields.last_mut().unwrap().quoted = true;
+            i = end + 1;
+            continue;
+        }
+        if process_quotes && c == '\'' && !quoted {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != '\'' {
+                end += 1;
+            }
+            let inner: String = chars[i+1..end.min(chars.len())].iter().collect();
+            let field = fields.last_mut().unwrap();
+            field.push_str(&inner, CharKind::Quoted);
+            field.quoted = true;
+            i = end + 1;
+            continue;
+        }
+        if c == '`' {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != '`' {
+                if chars[end] == '\\' {
+                    end += 1;
+                }
+                end += 1;
+            }
+            let inner: String = chars[i+1..end.min(chars.len())].iter().collect();
+            let output = command::substitute(&command::unescape_backticks(&inner), sh)?;
+            fields.last_mut().unwrap().push_str(&output, expanded_kind);
+            i = end + 1;
+            continue;
+        }
+        if process_quotes && !quoted && (c == '<' || c == '>') && chars.get(i+1) == Some(&'(') {
+            // <(cmd) and >(cmd) become a path to a pipe from or to cmd
+            let end = match find_command_subst_end(&chars, i+2, true, true) {
+                Ok(x) => x,
+                Err(_) => return Err(String::from("bad substitution: no closing `)'")),
+            };
+            let inner: String = chars[i+2..end].iter().collect();
+            let path = command::process_substitute(&inner, c == '<', sh)?;
+            fields.last_mut().unwrap().push_str(&path, CharKind::Quoted);
+            i = end + 1;
+            continue;
+        }
+        if c != '$' {
+            fields.last_mut().unwrap().chars.push((c, literal_kind));
+            i += 1;
+            continue;
+        }
+        // parse what follows $
+        let value;
+        match chars.get(i+1) {
+            Some('{') => {
+                let end = match find_closing(&chars, i+2, '{', '}') {
+                    Some(x) => x,
+                    None => return Err(String::from("bad substitution: no closing `}'")),
+                };
+                let inner: String = chars[i+2..end].iter().collect();
+                value = param::expand_braced(&inner, quoted, sh)?;
+                i = end + 1;
+            },
+            Some('(') => {
+                let end = match find_dollar_paren_end(&chars, i) {
+                    Some(x) => x,
+                    None => return Err(String::from("bad substitution: no closing `)'")),
+                };
+                // $(( ... )) is arithmetic when the inner ( closes right before the outer )
+                let is_arith = chars.get(i+2) == Some(&'(') && find_closing(&chars, i+3, '(', ')') == Some(end - 1);
+                if is_arith {
+                    let inner: String = chars[i+3..end-1].iter().collect();
+                    value = ParamValue::Single(expand_arith(&inner, sh)?.to_string());
+                } else {
+                    let inner: String = chars[i+2..end].iter().collect();
+                    value = ParamValue::Single(command::substitute(&inner, sh)?);
+                }
+                i = end + 1;
+            },
+            Some(x) if param::is_special(&x.to_string()) => {
+                value = param::lookup(&x.to_string(), sh).unwrap_or(ParamValue::Single(String::new()));
+                i += 2;
+            },
+            Some(x) if x.is_ascii_alphabetic() || *x == '_' => {
+                let mut end = i + 1;
+                while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+                    end += 1;
+                }
+                let name: String = chars[i+1..end].iter().collect();
+                value = param::lookup(&name, sh).unwrap_or(ParamValue::Single(String::new()));
+                i = end;
+            },
+            _ => {      // a lonely $
+                fields.last_mut().unwrap().chars.push((c, literal_kind));
+                i += 1;
+                continue;
+            }
+        };
+        match value {
+            ParamValue::Single(x) => fields.last_mut().unwrap().push_str(&x, expanded_kind),
+            ParamValue::Star(list) if quoted => {
+                // "$*" joins with the first char of IFS
+                let sep = match sh.get_var("IFS") {
+                    Some(x) => x.chars().next().map(|y| y.to_string()).unwrap_or_default(),
+                    None => String::from(" "),
+                };
+                fields.last_mut().unwrap().push_str(&list.join(&sep), expanded_kind);
+            },
+            ParamValue::List(list) | ParamValue::Star(list) => {
+                // one field per word
+                for (idx, word) in list.iter().enumerate() {
+                    if idx > 0 {
+                        fields.push(Field::new(quoted));
+                    }
+                    fields.last_mut().unwrap().push_str(word, expanded_kind);
+                }
+                if list.is_empty() && quoted {
+                    // "$@" with no positional parameters gives no field at all
+                    fields.last_mut().unwrap().quoted = false;
+                }
+            },
+            ParamValue::Fields(x) => append_fields(&mut fields, x),
+        };
+    }
+    Ok(fields)
+}
+
+/// expand $ inside an arithmetic expression, then evaluate it
+pub fn expand_arith(expr: &str, sh: &mut Mumsh) -> Result<i64, String> {
+    let fields = expand_text(expr, true, true, sh)?;
+    arith::eval(&join_fields(&fields), sh)
+}
+
+/// append fields to the last one, "a$@b" puts a before the first and b after the last word
+fn append_fields(fields: &mut Vec<Field>, new_fields: Vec<Field>) {
+    for (idx, field) in new_fields.into_iter().enumerate() {
+        if idx == 0 {
+            let last = fields.last_mut().unwrap();
+            last.chars.extend(field.chars);
+            last.quoted |= field.quoted;
+        } else {
+            fields.push(field);
+        }
+    }
+}
+
+/// index right after the unit at i, quotes, escapes, ${...} and $(...) are one unit
+fn next_index(chars: &[char], i: usize) -> usize {
+    match chars[i] {
+        '\\' => i + 2,
+        '\'' => match chars[i+1..].iter().position(|&c| c == '\'') {
+            Some(x) => i + x + 2,
+            None => chars.len(),
+        },
+        '`' => match chars[i+1..].iter().position(|&c| c == '`') {
+            Some(x) => i + x + 2,
+            None => chars.len(),
+        },
+        '\"' => find_quote_end(chars, i + 1) + 1,
+        '$' if chars.get(i+1) == Some(&'{') => {
+            find_closing(chars, i + 2, '{', '}').map_or(chars.len(), |x| x + 1)
+        },
+        '$' if chars.get(i+1) == Some(&'(') => {
+            find_dollar_paren_end(chars, i).map_or(chars.len(), |x| x + 1)
+        },
+        _ => i + 1,
+    }
+}
+
+/// find the last ) of the $(( ... )) or $( ... ) that starts with the $ at start, where the lexer ends it
+/// the commands of $( ... ) are parsed to find it, they run as a script so # starts a comment in them
+/// eg: "$(case a in a) echo y;; esac)" => 28
+fn find_dollar_paren_end(chars: &[char], start: usize) -> Option<usize> {
+    if chars.get(start + 2) == Some(&'(') {
+        let end = find_closing(chars, start + 3, '(', ')').filter(|x| chars.get(x + 1) == Some(&')'));
+        if let Some(x) = end {
+            return Some(x + 1);
+        }
+    }
+    find_command_subst_end(chars, start + 2, true, true).ok()
+}
+
+/// find the index of the " that closes a double quoted string starting at start
+/// ${...} and $(...) inside are skipped over
+fn find_quote_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\"' => return i,
+            '$' if chars.get(i+1) == Some(&'{') => {
+                i = find_closing(chars, i+2, '{', '}').unwrap_or(chars.len());
+            },
+            '$' if chars.get(i+1) == Some(&'(') => {
+                i = find_dollar_paren_end(chars, i).unwrap_or(chars.len());
+            },
+            _ => {},
+        };
+        i += 1;
+    }
+    chars.len()
+}
+
+/// find the index of the bracket that closes the one opened right before start
+/// quotes and backslashes are skipped over
+pub fn find_closing(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 1;
+    let mut quote: Option<char> = None;
+    let mut i = start;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            } else if c == '\\' && q == '\"' {
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        if c == '\\' {
+            i += 2;
+            continue;
+        }
+        if c == '\'' || c == '\"' || c == '`' {
+            quote = Some(c);
+        } else if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+/// split fields on the IFS characters that come from unquoted expansions
+/// IFS whitespace in a row counts as one separator, other IFS characters separate one by one
+fn split_fields(fields: Vec<Field>, sh: &Mumsh) -> Vec<Field> {
+    let ifs = sh.get_var("IFS").unwrap_or_else(|| String::from(" \t\n"));
+    let mut result = Vec::new();
+    for field in fields {
+        let mut current = Field { chars: Vec::new(), quoted: field.quoted };
+        let mut after_space = false;
+        for (c, kind) in field.chars {
+            if kind != CharKind::Expanded || !ifs.contains(c) {
+                current.chars.push((c, kind));
+                after_space = false;
+                continue;
+            }
+            if c.is_whitespace() {
+                if !current.chars.is_empty() || current.quoted {
+                    result.push(current);
+                    current = Field::default();
+                    after_space = true;
+                }
+                continue;
+            }
+            if !(current.chars.is_empty() && after_space) {
+                current.quoted = true;      // a:: gives an empty field in between
+                result.push(current);
+                current = Field::default();
+            }
+            after_space = false;
+        }
+        if !current.chars.is_empty() || current.quoted {
+            result.push(current);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, ast::Command};
+    use crate::types::CmdInfo;
+
+    /// the words of a simple command, parsed and expanded as the executor does
+    fn expand_line(line: &str, sh: &mut Mumsh) -> Vec<String> {
+        let list = parser::parse(line, true, true).unwrap();
+        let cmd = &list.items[0].and_or.first.cmds[0];
+        assert!(matches!(cmd, Command::Simple(_)));
+        let cmd_info = CmdInfo::from(cmd);
+        expand_tokens(&cmd_info.tokens, sh).unwrap().into_iter().map(|x| x.1).collect()
+    }
+
+    #[test]
+    fn quoted_parts() {
+        let mut sh = Mumsh::new();
+        sh.set_var("expand_x", "1  2");
+        assert_eq!(expand_line("a\"b c\"'d' 'e f' \"g h\"", &mut sh), ["ab cd", "e f", "g h"]);
+        assert_eq!(expand_line("$expand_x \"$expand_x\"x '$expand_x'y", &mut sh), ["1", "2", "1  2x", "$expand_xy"]);
+        assert_eq!(expand_line("a\\ b \"a\\\"b\" 'it'\\''s'", &mut sh), ["a b", "a\"b", "it's"]);
+        assert_eq!(expand_line("\"\" x''y", &mut sh), ["", "xy"]);
+    }
+
+    #[test]
+    fn ansi_c_parts() {
+        // $'...' is decoded by the lexer and handed on in single quotes
+        let mut sh = Mumsh::new();
+        assert_eq!(expand_line("$'a\\tb' x$'\\x41'y $'it\\'s'\"!\"", &mut sh), ["a\tb", "xAy", "it's!"]);
+        assert_eq!(expand_line("$'$HOME'x $'*'x", &mut sh), ["$HOMEx", "*x"]);
+    }
+
+    #[test]
+    fn quoted_specials() {
+        let mut sh = Mumsh::new();
+        // quoted braces, tildes and globs stay as they are
+        assert_eq!(expand_line("'{a,b}'x \"~\"/x '*'.nomatch a{\"b c\",d}", &mut sh), ["{a,b}x", "~/x", "*.nomatch", "ab c", "ad"]);
+        assert_eq!(expand_line("\"$(echo 'a  b')\"x", &mut sh), ["a  bx"]);
+    }
+}
@@ -0,0 +1,484 @@
+use crate::mumsh::Mumsh;
+use crate::expand::{self, CharKind, Field, pattern::Pattern};
+
+/// value of a parameter, $@ and $* expand to a list of words
+#[derive(Debug, Clone)]
+pub enum ParamValue {
+    Single(String),
+    List(Vec<String>),          // $@, one word each
+    Star(Vec<String>),          // $*, joined by the first char of IFS inside double quotes
+    Fields(Vec<Field>)          // an already expanded word, eg: the default of ${var:-word}
+}
+
+/// check whether name is a valid variable name
+/// eg: HOME, _x1 => true, 1x, a-b => false
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(x) if x.is_ascii_alphabetic() || x == '_' => {},
+        _ => return false,
+    };
+    chars.all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+/// check whether name is a special parameter or a positional parameter
+/// eg: ? $ ! # @ * 0 12
+pub fn is_special(name: &str) -> bool {
+    if name.len() == 1 && "?$!#@*-".contains(name) {
+        return true;
+    }
+    !name.is_empty() && name.chars().all(|x| x.is_ascii_digit())
+}
+
+/// look up the value of a parameter, None if unset
+pub fn lookup(name: &str, sh: &Mumsh) -> Option<ParamValue> {
+    match name {
+        "?" => Some(ParamValue::Single(sh.last_status.to_string())),
+        "$" => Some(ParamValue::Single(sh.pid.to_string())),
+        "!" => {
+            if sh.last_bg_pid == 0 {
+                return None;
+            }
+            Some(ParamValue::Single(sh.last_bg_pid.to_string()))
+        },
+        "#" => Some(ParamValue::Single((sh.args.len() - 1).to_string())),
+        "@" => Some(ParamValue::List(sh.args[1..].to_vec())),
+        "*" => Some(ParamValue::Star(sh.args[1..].to_vec())),
+        "-" => {
+            if sh.is_interactive {
+                return Some(ParamValue::Single(String::from("i")));
+            }
+            Some(ParamValue::Single(String::new()))
+        },
+        _ => {
+            if let Ok(n) = name.parse::<usize>() {
+                return sh.args.get(n).map(|x| ParamValue::Single(x.clone()));
+            }
+            sh.get_var(name).map(ParamValue::Single)
+        }
+    }
+}
+
+/// expand the inside of ${...}
+/// eg: ${HOME} => inner: "HOME"
+///     ${var:-default}, ${var:=default}, ${var:?message}, ${var:+alternative} and the forms without :
+///     ${#var}, ${var#pattern}, ${var##pattern}, ${var%pattern}, ${var%%pattern}
+///     ${var/pattern/string}, ${var//pattern/string}, ${var/#pattern/string}, ${var/%pattern/string}
+///     ${var:offset}, ${var:offset:length}, ${var^^}, ${var^}, ${var,,}, ${var,}
+pub fn expand_braced(inner: &str, quoted: bool, sh: &mut Mumsh) -> Result<ParamValue, String> {
+    let bad_substitution = format!("${{{}}}: bad substitution", inner);
+    // ${#var}, but ${#} and ${#-...} are about $#
+    if inner.len() > 1 && inner.starts_with('#') {
+        let name = &inner[1..];
+        if is_name(name) || is_special(name) {
+            let length = match lookup(name, sh) {
+                Some(ParamValue::List(x)) | Some(ParamValue::Star(x)) => x.len(),
+                Some(ParamValue::Single(x)) => x.chars().count(),
+                _ => 0,
+            };
+            return Ok(ParamValue::Single(length.to_string()));
+        }
+    }
+    let name_len = parse_name(inner);
+    if name_len == 0 {
+        return Err(bad_substitution);
+    }
+    let name = &inner[..name_len];
+    let rest = &inner[name_len..];
+    let value = lookup(name, sh);
+    if rest.is_empty() {
+        return Ok(value.unwrap_or(ParamValue::Single(String::new())));
+    }
+    // ${var:-word} and friends, the : also treats an empty value as unset
+    for op in [":-", ":=", ":?", ":+", "-", "=", "?", "+"] {
+        if let Some(word) = rest.strip_prefix(op) {
+            let is_set = match &value {
+                None => false,
+                Some(ParamValue::Single(x)) => !(op.starts_with(':') && x.is_empty()),
+                Some(ParamValue::List(x)) | Some(ParamValue::Star(x)) => !(op.starts_with(':') && x.is_empty()),
+                Some(ParamValue::Fields(_)) => true,
+            };
+            let use_word = if op.ends_with('+') { is_set } else { !is_set };
+            if !use_word {
+                if op.ends_with('+') {
+                    return Ok(ParamValue::Single(String::new()));
+                }
+                return Ok(value.unwrap());
+            }
+            let fields = expand::expand_text(word, quoted, true, sh)?;
+            if op.ends_with('=') {
+                if !is_name(name) {
+                    return Err(format!("{}: cannot assign in this way", name));
+                }
+                let word = expand::join_fields(&fields);
+                sh.set_var(name, &word);
+                return Ok(ParamValue::Single(word));
+            }
+            if op.ends_with('?') {
+                let mut message = expand::join_fields(&fields);
+                if message.is_empty() {
+                    message = String::from("parameter null or not set");
+                }
+                return Err(format!("{}: {}", name, message));
+            }
+            // unquoted text of the word is split like any other expansion result
+            let mut fields = fields;
+            for field in fields.iter_mut() {
+                for x in field.chars.iter_mut() {
+                    if x.1 == CharKind::Literal {
+                        x.1 = CharKind::Expanded;
+                    }
+                }
+            }
+            return Ok(ParamValue::Fields(fields));
+        }
+    }
+    // ${var:offset:length}
+    if let Some(range) = rest.strip_prefix(':') {
+        let (offset, length) = match range.split_once(':') {
+            Some((x, y)) => (x, Some(y)),
+            None => (range, None),
+        };
+        let offset = parse_number(offset, sh)?;
+        let length = match length {
+            Some(x) => Some(parse_number(x, sh)?),
+            None => None,
+        };
+        return match value {
+            Some(ParamValue::List(x)) | Some(ParamValue::Star(x)) => {
+                // ${@:offset:length} counts from $0
+                let mut list = sh.args[..1].to_vec();
+                list.extend(x);
+                match substring(&list, offset, length) {
+                    Some(y) => Ok(ParamValue::List(y)),
+                    None => Err(format!("{}: substring expression < 0", length.unwrap_or(0))),
+                }
+            },
+            Some(ParamValue::Single(x)) => {
+                let chars: Vec<char> = x.chars().collect();
+                match substring(&chars, offset, length) {
+                    Some(y) => Ok(ParamValue::Single(y.iter().collect())),
+                    None => Err(format!("{}: substring expression < 0", length.unwrap_or(0))),
+                }
+            },
+            _ => Ok(ParamValue::Single(String::new())),
+        };
+    }
+    // the rest work on each word of the value
+    let op = ["##", "#", "%%", "%", "//", "/#", "/%", "/", "^^", "^", ",,", ","]
+        .into_iter()
+        .find(|x| rest.starts_with(x));
+    let op = match op {
+        Some(x) => x,
+        None => return Err(bad_substitution),
+    };
+    let word = &rest[op.len()..];
+    let (pattern_word, replacement) = if op.starts_with('/') {
+        match split_replacement(word) {
+            (x, Some(y)) => {
+                let replacement = expand::join_fields(&expand::expand_text(y, quoted, true, sh)?);
+                (x, replacement)
+            },
+            (x, None) => (x, String::new()),
+        }
+    } else {
+        (word, String::new())
+    };
+    // the pattern is not quoted by double quotes around the whole ${...}, only by its own quotes
+    let pattern_fields = expand::expand_text(pattern_word, false, true, sh)?;
+    let mut pattern_string = String::new();
+    for field in pattern_fields {
+        pattern_string.push_str(&field.pattern());
+    }
+    let pattern = Pattern::new(&pattern_string);
+    let apply = |x: &str| -> String {
+        match op {
+            "#" | "##" => remove_prefix(x, &pattern, op == "##"),
+            "%" | "%%" => remove_suffix(x, &pattern, op == "%%"),
+            "^^" | "^" | ",," | "," => change_case(x, &pattern, pattern_string.is_empty(), op),
+            _ => replace(x, &pattern, &replacement, op),
+        }
+    };
+    match value {
+        Some(ParamValue::List(x)) => Ok(ParamValue::List(x.iter().map(|y| apply(y)).collect())),
+        Some(ParamValue::Star(x)) => Ok(ParamValue::Star(x.iter().map(|y| apply(y)).collect())),
+        Some(ParamValue::Single(x)) => Ok(ParamValue::Single(apply(&x))),
+        _ => Ok(ParamValue::Single(String::new())),
+    }
+}
+
+/// length of the parameter name at the beginning of inner
+/// eg: HOME:-x => 4, 12 => 2, @:1 => 1
+fn parse_name(inner: &str) -> usize {
+    let chars: Vec<char> = inner.chars().collect();
+    match chars.first() {
+        Some(x) if x.is_ascii_digit() => chars.iter().take_while(|y| y.is_ascii_digit()).count(),
+        Some(x) if "?$!#@*-".contains(*x) => 1,
+        Some(x) if x.is_ascii_alphabetic() || *x == '_' => {
+            chars.iter().take_while(|y| y.is_ascii_alphanumeric() || **y == '_').count()
+        },
+        _ => 0,
+    }
+}
+
+/// evaluate offset or length of ${var:offset:length}
+fn parse_number(text: &str, sh: &mut Mumsh) -> Result<i64, String> {
+    expand::expand_arith(text, sh)
+}
+
+/// take a substring, negative offset counts from the end, negative length stops before the end
+/// None if the end lies before the start
+fn substring<T: Clone>(items: &[T], offset: i64, length: Option<i64>) -> Option<Vec<T>> {
+    let len = items.len() as i64;
+    let start = if offset < 0 { len + offset } else { offset };
+    if start < 0 || start > len {
+        return Some(Vec::new());
+    }
+    let end = match length {
+        None => len,
+        Some(x) if x < 0 => len + x,
+        Some(x) => (start + x).min(len),
+    };
+    if end < start {
+        return None;
+    }
+    Some(items[start as usize..end as usize].to_vec())
+}
+
+/// split pattern/string of ${var/pattern/string} on the first unquoted /
+fn split_replacement(word: &str) -> (&str, Option<&str>) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in word.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '\'' | '\"' => quote = Some(c),
+            '/' => return (&word[..i], Some(&word[i+1..])),
+            _ => {},
+        };
+    }
+    (word, None)
+}
+
+fn remove_prefix(text: &str, pattern: &Pattern, longest: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut ends: Vec<usize> = (0..=chars.len()).collect();
+    if longest {
+        ends.reverse();
+    }
+    for end in ends {
+        if pattern.matches_chars(&chars[..end]) {
+            return chars[end..].iter().collect();
+        }
+    }
+    text.to_string()
+}
+
+fn remove_suffix(text: &str, pattern: &Pattern, longest: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut starts: Vec<usize> = (0..=chars.len()).collect();
+    if !longest {
+        starts.reverse();
+    }
+    for start in starts {
+        if pattern.matches_chars(&chars[start..]) {
+            return chars[..start].iter().collect();
+        }
+    }
+    text.to_string()
+}
+
+/// ${var/pattern/string}, the longest match is replaced
+/// // replaces all matches, /# only matches at the beginning, /% only at the end
+fn replace(text: &str, pattern: &Pattern, replacement: &str, op: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let len = chars.len();
+    match op {
+        "/#" => {
+            for end in (0..=len).rev() {
+                if pattern.matches_chars(&chars[..end]) {
+                    return format!("{}{}", replacement, chars[end..].iter().collect::<String>());
+                }
+            }
+            text.to_string()
+        },
+        "/%" => {
+            for start in 0..=len {
+                if pattern.matches_chars(&chars[start..]) {
+                    return format!("{}{}", chars[..start].iter().collect::<String>(), replacement);
+                }
+            }
+            text.to_string()
+        },
+        _ => {
+            let mut result = String::new();
+            let mut start = 0;
+            let mut replaced = false;
+            while start < len {
+                if !(replaced && op == "/") {
+                    let matched = (start+1..=len).rev().find(|end| pattern.matches_chars(&chars[start..*end]));
+                    if let Some(end) = matched {
+                        result.push_str(replacement);
+                        start = end;
+                        replaced = true;
+                        continue;
+                    }
+                }
+                result.push(chars[start]);
+                start += 1;
+            }
+            result
+        }
+    }
+}
+
+/// ${var^^} and ${var,,} change every matching character, ${var^} and ${var,} only the first
+fn change_case(text: &str, pattern: &Pattern, match_all: bool, op: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in text.chars().enumerate() {
+        let is_target = (op.len() == 2 || i == 0) && (match_all || pattern.matches(&c.to_string()));
+        if !is_target {
+            result.push(c);
+        } else if op.starts_with('^') {
+            result.extend(c.to_uppercase());
+        } else {
+            result.extend(c.to_lowercase());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a shell with x=abcdef, e set but empty and u unset, and positional parameters a "b c" d
+    fn shell() -> Mumsh {
+        let mut sh = Mumsh::new();
+        sh.vars.insert(String::from("x"), String::from("abcdef"));
+        sh.vars.insert(String::from("e"), String::new());
+        sh.args = ["mumsh", "a", "b c", "d"].iter().map(|x| x.to_string()).collect();
+        sh
+    }
+
+    /// the value of ${inner} as one string, words joined by spaces
+    fn braced(inner: &str, sh: &mut Mumsh) -> Result<String, String> {
+        Ok(match expand_braced(inner, false, sh)? {
+            ParamValue::Single(x) => x,
+            ParamValue::List(x) | ParamValue::Star(x) => x.join(" "),
+            ParamValue::Fields(x) => expand::join_fields(&x),
+        })
+    }
+
+    fn braced_ok(inner: &str) -> String {
+        braced(inner, &mut shell()).unwrap()
+    }
+
+    #[test]
+    fn names() {
+        assert!(is_name("_x1") && is_name("HOME"));
+        assert!(!is_name("1x") && !is_name("a-b") && !is_name(""));
+        assert!(is_special("?") && is_special("12") && !is_special("a"));
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(braced_ok("x:-"), "abcdef");
+        assert_eq!(braced_ok("u:-"), "");
+        assert_eq!(braced_ok("e:-d"), "d");
+        assert_eq!(braced_ok("e-d"), "");
+        assert_eq!(braced_ok("u-d"), "d");
+        assert_eq!(braced_ok("x:+alt"), "alt");
+        assert_eq!(braced_ok("e:+alt"), "");
+        assert_eq!(braced_ok("e+alt"), "alt");
+        let mut sh = shell();
+        assert_eq!(braced("e:=set", &mut sh), Ok(String::from("set")));
+        assert_eq!(sh.get_var("e"), Some(String::from("set")));
+        assert_eq!(braced("u:?gone", &mut sh), Err(String::from("u: gone")));
+        assert_eq!(braced("u?", &mut sh), Err(String::from("u: parameter null or not set")));
+        assert_eq!(braced("1:=x", &mut sh), Ok(String::from("a")));
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(braced_ok("#x"), "6");
+        assert_eq!(braced_ok("#e"), "0");
+        assert_eq!(braced_ok("#u"), "0");
+        assert_eq!(braced_ok("#"), "3");
+        assert_eq!(braced_ok("#@"), "3");
+        assert_eq!(braced_ok("#2"), "3");
+    }
+
+    #[test]
+    fn removals() {
+        // an empty pattern removes nothing
+        assert_eq!(braced_ok("x#"), "abcdef");
+        assert_eq!(braced_ok("x##"), "abcdef");
+        assert_eq!(braced_ok("x%"), "abcdef");
+        assert_eq!(braced_ok("x#a"), "bcdef");
+        assert_eq!(braced_ok("x#*c"), "def");
+        assert_eq!(braced_ok("x##*[ce]"), "f");
+        assert_eq!(braced_ok("x%d*"), "abc");
+        assert_eq!(braced_ok("x%%[b-d]*"), "a");
+        assert_eq!(braced_ok("x#\"*\"c"), "abcdef");
+        // each word of $@ on its own
+        assert_eq!(braced_ok("@#?"), "  c ");
+    }
+
+    #[test]
+    fn replacements() {
+        // an empty pattern replaces nothing
+        assert_eq!(braced_ok("x/"), "abcdef");
+        assert_eq!(braced_ok("x//"), "abcdef");
+        assert_eq!(braced_ok("x/c"), "abdef");
+        assert_eq!(braced_ok("x/c/X"), "abXdef");
+        assert_eq!(braced_ok("x//[ace]/-"), "-b-d-f");
+        assert_eq!(braced_ok("x/#ab/S"), "Scdef");
+        assert_eq!(braced_ok("x/#c/S"), "abcdef");
+        assert_eq!(braced_ok("x/%ef/E"), "abcdE");
+        assert_eq!(braced_ok("u/a/b"), "");
+    }
+
+    #[test]
+    fn substrings() {
+        assert_eq!(braced_ok("x:2"), "cdef");
+        assert_eq!(braced_ok("x:1:2"), "bc");
+        assert_eq!(braced_ok("x:10"), "");
+        // negative offsets count from the end, negative lengths stop before it
+        assert_eq!(braced_ok("x: -2"), "ef");
+        assert_eq!(braced_ok("x: -2:1"), "e");
+        assert_eq!(braced_ok("x:1:-1"), "bcde");
+        assert_eq!(braced_ok("x: -3:-1"), "de");
+        assert_eq!(braced_ok("x: -10"), "");
+        assert_eq!(braced_ok("x:(-2)"), "ef");
+        assert_eq!(braced("x:1:-6", &mut shell()), Err(String::from("-6: substring expression < 0")));
+        // ${@:offset} counts from $0
+        assert_eq!(braced_ok("@:2"), "b c d");
+        assert_eq!(braced_ok("@: -1"), "d");
+        assert_eq!(braced_ok("@:0:2"), "mumsh a");
+    }
+
+    #[test]
+    fn cases() {
+        assert_eq!(braced_ok("x^"), "Abcdef");
+        assert_eq!(braced_ok("x^^"), "ABCDEF");
+        assert_eq!(braced_ok("x^^[ace]"), "AbCdEf");
+        assert_eq!(braced_ok("@^"), "A B c D");
+    }
+
+    #[test]
+    fn bad_substitutions() {
+        assert_eq!(braced("", &mut shell()), Err(String::from("${}: bad substitution")));
+        assert_eq!(braced("x!", &mut shell()), Err(String::from("${x!}: bad substitution")));
+    }
+}
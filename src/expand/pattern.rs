@@ -0,0 +1,231 @@
+/// one element of a compiled shell pattern
+#[derive(Debug, Clone)]
+enum PatternToken {
+    Char(char),
+    AnyChar,                                // ?
+    AnyString,                              // *
+    Class(bool, Vec<ClassItem>)             // [...], true if negated with ! or ^
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(String)                           // [:digit:] and friends
+}
+
+/// a shell pattern, as used by globbing, case and ${var#pattern}
+/// a backslash makes the next character literal
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tokens: Vec<PatternToken>
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Pattern {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(PatternToken::Char(chars[i+1]));
+                    i += 2;
+                    continue;
+                },
+                '?' => tokens.push(PatternToken::AnyChar),
+                '*' => {
+                    // ** is the same as * when matching a string
+                    if !matches!(tokens.last(), Some(PatternToken::AnyString)) {
+                        tokens.push(PatternToken::AnyString);
+                    }
+                },
+                '[' => {
+                    if let Some((class, end)) = parse_class(&chars, i) {
+                        tokens.push(class);
+                        i = end + 1;
+                        continue;
+                    }
+                    tokens.push(PatternToken::Char('['));
+                },
+                x => tokens.push(PatternToken::Char(x)),
+            };
+            i += 1;
+        }
+        Pattern { tokens }
+    }
+
+    /// check whether the pattern has any special character, otherwise it only matches itself
+    pub fn is_literal(&self) -> bool {
+        self.tokens.iter().all(|x| matches!(x, PatternToken::Char(_)))
+    }
+
+    /// the text a literal pattern matches, eg: a\*b => a*b
+    pub fn literal(&self) -> String {
+        self.tokens.iter().filter_map(|x| match x {
+            PatternToken::Char(c) => Some(*c),
+            _ => None,
+        }).collect()
+    }
+
+    /// check whether the pattern starts with a literal dot, needed to match hidden files
+    pub fn starts_with_dot(&self) -> bool {
+        matches!(self.tokens.first(), Some(PatternToken::Char('.')))
+    }
+
+    /// match the whole text against the pattern
+    pub fn matches(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.matches_chars(&chars)
+    }
+
+    pub fn matches_chars(&self, text: &[char]) -> bool {
+        // iterative matching, backtracking only to the last *
+        let mut t = 0;
+        let mut p = 0;
+        let mut star: Option<(usize, usize)> = None;       // (index of *, text index it matched up to)
+        while t < text.len() {
+            let mut advanced = false;
+            if p < self.tokens.len() {
+                match &self.tokens[p] {
+                    PatternToken::AnyString => {
+                        star = Some((p, t));
+                        p += 1;
+                        continue;
+                    },
+                    PatternToken::AnyChar => advanced = true,
+                    PatternToken::Char(x) => advanced = *x == text[t],
+                    PatternToken::Class(negated, items) => advanced = class_matches(items, text[t]) != *negated,
+                };
+            }
+            if advanced {
+                t += 1;
+                p += 1;
+            } else if let Some((star_p, star_t)) = star {
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            } else {
+                return false;
+            }
+        }
+        while p < self.tokens.len() && matches!(self.tokens[p], PatternToken::AnyString) {
+            p += 1;
+        }
+        p == self.tokens.len()
+    }
+}
+
+/// parse a bracket expression starting at chars[start] == '['
+/// returns the class and the index of the closing ]
+fn parse_class(chars: &[char], start: usize) -> Option<(PatternToken, usize)> {
+    let mut i = start + 1;
+    let mut negated = false;
+    let mut items = Vec::new();
+    if i < chars.len() && (chars[i] == '!' || chars[i] == '^') {
+        negated = true;
+        i += 1;
+    }
+    let first = i;
+    while i < chars.len() {
+        let c = chars[i];
+        // ] right after [ or [! is a literal ]
+        if c == ']' && i > first {
+            return Some((PatternToken::Class(negated, items), i));
+        }
+        if c == '[' && chars.get(i+1) == Some(&':') {
+            let rest: String = chars[i+2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                let name = rest[..end].to_string();
+                i += 2 + name.chars().count() + 2;
+                items.push(ClassItem::Named(name));
+                continue;
+            }
+        }
+        let mut lower = c;
+        if c == '\\' && i + 1 < chars.len() {
+            i += 1;
+            lower = chars[i];
+        }
+        if chars.get(i+1) == Some(&'-') && i + 2 < chars.len() && chars[i+2] != ']' {
+            let mut upper = chars[i+2];
+            i += 2;
+            if upper == '\\' && i + 1 < chars.len() {
+                i += 1;
+                upper = chars[i];
+            }
+            items.push(ClassItem::Range(lower, upper));
+        } else {
+            items.push(ClassItem::Char(lower));
+        }
+        i += 1;
+    }
+    None
+}
+
+fn class_matches(items: &[ClassItem], c: char) -> bool {
+    items.iter().any(|item| match item {
+        ClassItem::Char(x) => *x == c,
+        ClassItem::Range(x, y) => *x <= c && c <= *y,
+        ClassItem::Named(name) => match name.as_str() {
+            "alnum" => c.is_alphanumeric(),
+            "alpha" => c.is_alphabetic(),
+            "blank" => c == ' ' || c == '\t',
+            "cntrl" => c.is_control(),
+            "digit" => c.is_ascii_digit(),
+            "graph" => !c.is_whitespace() && !c.is_control(),
+            "lower" => c.is_lowercase(),
+            "print" => !c.is_control(),
+            "punct" => c.is_ascii_punctuation(),
+            "space" => c.is_whitespace(),
+            "upper" => c.is_uppercase(),
+            "xdigit" => c.is_ascii_hexdigit(),
+            _ => false,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(Pattern::new("*.rs").matches("main.rs"));
+        assert!(!Pattern::new("*.rs").matches("main.rc"));
+        assert!(Pattern::new("a?c").matches("abc"));
+        assert!(!Pattern::new("a?c").matches("ac"));
+        assert!(Pattern::new("*a*b*").matches("xxaxxbxx"));
+        assert!(Pattern::new("").matches(""));
+    }
+
+    #[test]
+    fn double_star() {
+        // ** matches like * when matching a string, slashes included
+        assert!(Pattern::new("**").matches("a/b/c"));
+        assert!(Pattern::new("a/**.rs").matches("a/b/c.rs"));
+        assert!(!Pattern::new("**.rs").matches("a/b/c.rc"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(Pattern::new("[abc]").matches("b"));
+        assert!(!Pattern::new("[abc]").matches("d"));
+        assert!(Pattern::new("[a-c]x").matches("cx"));
+        assert!(Pattern::new("[!a-c]").matches("d"));
+        assert!(!Pattern::new("[^a-c]").matches("a"));
+        assert!(Pattern::new("[[:digit:]][[:alpha:]]").matches("1z"));
+        assert!(!Pattern::new("[[:upper:]]").matches("a"));
+        assert!(Pattern::new("[]]").matches("]"));
+        // an unclosed [ is literal
+        assert!(Pattern::new("[a").matches("[a"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(Pattern::new("a\\*").matches("a*"));
+        assert!(!Pattern::new("a\\*").matches("ab"));
+        assert!(Pattern::new("a\\*b").is_literal());
+        assert_eq!(Pattern::new("a\\*b").literal(), "a*b");
+    }
+}
@@ -0,0 +1,77 @@
+use nix::unistd::User;
+
+use crate::mumsh::Mumsh;
+
+/// expand the tilde prefix at the start of text, giving the directory and the prefix length
+/// the prefix runs up to the first / or :
+/// eg: "~/src" => ($HOME, 1), "~+" => ($PWD, 2), "~-" => ($OLDPWD, 2), "~root/x" => ("/root", 5)
+pub fn expand_prefix(text: &str, sh: &Mumsh) -> Option<(String, usize)> {
+    let prefix: String = text.chars()
+        .skip(1)
+        .take_while(|&c| c != '/' && c != ':')
+        .collect();
+    // a quoted or escaped prefix is not a tilde prefix
+    if prefix.chars().any(|c| "\"'`\\$".contains(c)) {
+        return None;
+    }
+    let dir = match prefix.as_str() {
+        "" => match sh.get_var("HOME") {
+            Some(x) => x,
+            None => User::from_uid(nix::unistd::getuid()).ok()??.dir.display().to_string(),
+        },
+        "+" => sh.get_var("PWD").unwrap_or_else(|| sh.current_dir.clone()),
+        "-" => sh.get_var("OLDPWD")?,
+        name => User::from_name(name).ok()??.dir.display().to_string(),
+    };
+    Some((dir, prefix.chars().count() + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a shell whose HOME, PWD and OLDPWD are its own variables, the environment is left alone
+    fn shell() -> Mumsh {
+        let mut sh = Mumsh::new();
+        for (name, value) in [("HOME", "/home/me"), ("PWD", "/here"), ("OLDPWD", "/there")] {
+            sh.vars.insert(name.to_string(), value.to_string());
+        }
+        sh
+    }
+
+    fn expand(text: &str) -> Option<(String, usize)> {
+        expand_prefix(text, &shell())
+    }
+
+    #[test]
+    fn home() {
+        assert_eq!(expand("~"), Some((String::from("/home/me"), 1)));
+        assert_eq!(expand("~/src"), Some((String::from("/home/me"), 1)));
+        assert_eq!(expand("~:/bin"), Some((String::from("/home/me"), 1)));
+    }
+
+    #[test]
+    fn directories() {
+        assert_eq!(expand("~+/x"), Some((String::from("/here"), 2)));
+        assert_eq!(expand("~-"), Some((String::from("/there"), 2)));
+        let mut sh = shell();
+        sh.vars.remove("OLDPWD");
+        if std::env::var_os("OLDPWD").is_none() {
+            assert_eq!(expand_prefix("~-", &sh), None);
+        }
+    }
+
+    #[test]
+    fn users() {
+        assert_eq!(expand("~root/x"), Some((String::from("/root"), 5)));
+        assert_eq!(expand("~no_such_user_here"), None);
+    }
+
+    #[test]
+    fn quoted_prefixes() {
+        // a quoted or escaped prefix is left as it is
+        assert_eq!(expand("~'root'"), None);
+        assert_eq!(expand("~\\root"), None);
+        assert_eq!(expand("~$USER"), None);
+    }
+}
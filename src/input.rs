@@ -1,86 +1,74 @@
 use linefeed::{Function, Prompter, Terminal};
+use nix::unistd::read;
 use std::io::{self, ErrorKind};
 
-use crate::parser;
+use crate::parser::{self, ParseError};
 
-pub struct InputCheck;
+pub struct InputCheck {
+    pub comments: bool      // interactive_comments, whether # starts a comment
+}
+
+/// prompts for input that is not complete yet, named after what is still open
+/// the empty one is for a trailing backslash, it comes first so that "> " typed after another prompt stays
+const PROMPTS: [&str; 20] = [
+    "", "dquote", "quote", "bquote", "braceparam", "cmdsubst", "mathsubst", "math", "cmdand",
+    "cmdor", "pipe", "heredoc", "if", "while", "until", "for", "case", "function", "brace", "subsh",
+];
 
 impl<Term: Terminal> Function<Term> for InputCheck {
     fn execute(&self, prompter: &mut Prompter<Term>, count: i32, _ch: char) -> io::Result<()> {
-        let buf = prompter.buffer();
-        let split_result = parser::parse_line::split_line(buf);
-        let mut complete_prompt = String::new();
-        match parser::parse_line::check_split_result(&split_result) {
-            Ok(_) => {},
-            Err(e) => {
-                match e.kind() {
-                    ErrorKind::InvalidInput => return Err(e),
-                    ErrorKind::Other => {
-                        match e.to_string().as_str() {
-                            "&&" => complete_prompt = String::from("cmdand> "),
-                            "||" => complete_prompt = String::from("cmdor> "),
-                            _ => eprintln!("split_result: unknown error string")
-                        };
-                    },
-                    _ => eprintln!("split_result: unknown error"),
+        let buf = remove_multiline_prompt(prompter.buffer());
+        match parser::parse(&buf, false, self.comments) {
+            Ok(_) => prompter.accept_input(),
+            Err(ParseError::Syntax(e)) => Err(io::Error::new(ErrorKind::InvalidInput, e)),
+            Err(ParseError::Incomplete(x)) => {
+                if count > 0 {
+                    match prompter.insert(count as usize, '\n') {
+                        Ok(_) => {},
+                        Err(e) => eprintln!("input-check error: {}", e),
+                    };
+                    match prompter.insert_str(&format!("{}> ", x)) {
+                        Ok(_) => {},
+                        Err(e) => eprintln!("input-check error: {}", e),
+                    };
                 }
+                Ok(())
             }
-        };
+        }
+    }
+}
 
-        let parse_result = parser::parse_line::line_to_tokens(buf);
+/// remove the prompts inserted into the buffer, the newlines stay
+pub fn remove_multiline_prompt(line: &str) -> String {
+    let mut line = line.to_string();
+    for prompt in PROMPTS.iter() {
+        line = line.replace(&format!("\n{}> ", prompt), "\n");
+    }
+    line
+}
 
-        if !complete_prompt.is_empty() && count > 0 {       
-            match prompter.insert(count as usize, '\n') {
-                Ok(_) => {},
-                Err(e) => eprintln!("input-check error: {}", e),
-            };
-            match prompter.insert_str(&complete_prompt) {
-                Ok(_) => {},
-                Err(e) => eprintln!("input-check error: {}", e),
-            };
-            return Ok(());
-        }
+/// the lines of a non-interactive stdin, eg: echo cmds | mumsh
+/// read one byte at a time, so that what comes after a command is left for it to read
+pub struct StdinLines;
 
-        if parse_result.is_complete {
-            return prompter.accept_input();
-        } else if count > 0 {
-            let mut char_mismatch = ' ';
-            complete_prompt = String::new();
-            // check heredoc, other wise incomplete quote or braces
-            if !parse_result.here_doc.is_empty() {
-                complete_prompt.push_str("heredoc> ");
-            } else {
-                match parse_result.unmatched.chars().last() {
-                    Some(x) => char_mismatch = x,
-                    None => {},
-                };
-                match char_mismatch {
-                    '\"' | '\'' | '`' => complete_prompt.push_str("dquote> "),
-                    '{' => complete_prompt.push_str("braceparam> "),
-                    '(' => complete_prompt.push_str("cmdsubst> "),
-                    _ => complete_prompt.push_str("> ")
-                };
-            }
-            match prompter.insert(count as usize, '\n') {
-                Ok(_) => {},
-                Err(e) => eprintln!("input-check error: {}", e),
-            };
-            match prompter.insert_str(&complete_prompt) {
-                Ok(_) => {},
-                Err(e) => eprintln!("input-check error: {}", e),
+impl Iterator for StdinLines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            match read(0, &mut byte) {
+                Ok(0) => break,
+                Ok(_) if byte[0] == b'\n' => return Some(String::from_utf8_lossy(&line).to_string()),
+                Ok(_) => line.push(byte[0]),
+                Err(nix::Error::EINTR) => continue,
+                Err(e) => {
+                    eprintln!("mumsh: {}", e.desc());
+                    break;
+                },
             };
         }
-        Ok(())
+        if line.is_empty() { None } else { Some(String::from_utf8_lossy(&line).to_string()) }
     }
 }
-
-pub fn remove_multiline_prompt(line: &str) -> String {
-    line.replace("\ndquote> ", "")
-        .replace("\nbraceparam> ", "")
-        .replace("\ncmdsubst> ", "")
-        .replace("\ncmdand> ", "")
-        .replace("\ncmdor> ", "")
-        .replace("\nheredoc> ", "\n")     // trick: retains \n for heredoc
-        .replace("\n> ", "")
-        .to_string()
-}
@@ -1,9 +1,14 @@
+use std::{env, fs, process};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
 
 use colored::{self, Colorize};
 use linefeed::{Interface, ReadResult, Command};
 use libc;
-use nix::{unistd::{isatty, tcgetpgrp, getpgrp, Pid, getpid, setpgid}, sys::signal::kill};
+use nix::{errno::Errno, unistd::{isatty, tcgetpgrp, getpgrp, Pid, getpid, setpgid, pipe2, read}, sys::signal::kill};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use termios::{*, os::linux::ECHOCTL};
 
 mod executor;
@@ -13,19 +18,79 @@ mod types;
 mod mumsh;
 mod common;
 mod builtin;
+mod expand;
+
+// the write end of the pipe SIGCHLD is noted in
+static SIGCHLD_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_sigchld(_: libc::c_int) {
+    // only async-signal-safe calls, errno is kept for the code the signal interrupted
+    // a full pipe is fine, the read loop has a byte waiting already
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(SIGCHLD_FD.load(Ordering::Relaxed), b"c".as_ptr() as *const libc::c_void, 1);
+        *libc::__errno_location() = errno;
+    }
+}
+
+/// whether children changed state since the last call, the pipe gets emptied
+fn children_changed(fd: i32) -> bool {
+    let mut buf = [0; 64];
+    let mut changed = false;
+    while let Ok(n) = read(fd, &mut buf) {
+        if n == 0 {
+            break;
+        }
+        changed = true;
+    }
+    changed
+}
 
 fn main() {
     let mut sh = mumsh::Mumsh::new();
     let shell_is_interactive;
     let mut shell_pgid = getpgrp();
-    match isatty(1) {
-        Ok(x) => shell_is_interactive = x,
-        Err(e) => {
+    let args: Vec<String> = env::args().collect();
+    // mumsh -c 'cmd' [name [args...]]
+    if args.len() > 1 && args[1] == "-c" {
+        let cmd = match args.get(2) {
+            Some(x) => x,
+            None => {
+                eprintln!("mumsh: -c: option requires an argument");
+                process::exit(2);
+            }
+        };
+        if args.len() > 3 {
+            sh.args = args[3..].to_vec();
+        }
+        process::exit(executor::run_script(cmd, &mut sh));
+    }
+    // mumsh script.sh [args...]
+    if args.len() > 1 {
+        let script = match fs::read_to_string(&args[1]) {
+            Ok(x) => x,
+            Err(e) => {
+                let errno = Errno::from_i32(e.raw_os_error().unwrap_or(0));
+                eprintln!("mumsh: {}: {}", args[1], errno.desc());
+                process::exit(127);
+            }
+        };
+        sh.args = args[1..].to_vec();
+        process::exit(executor::run_script(&script, &mut sh));
+    }
+    match (isatty(0), isatty(1)) {
+        (Ok(x), Ok(y)) => shell_is_interactive = x && y,
+        (Err(e), _) | (_, Err(e)) => {
             eprintln!("{}", e);
             return;
         }
     };
+    // echo cmds | mumsh
+    if !shell_is_interactive {
+        process::exit(executor::run_lines(&mut input::StdinLines, &mut sh));
+    }
     if shell_is_interactive {
+        sh.is_interactive = true;
         // loop until mumsh is in foreground
         while tcgetpgrp(1).unwrap() != shell_pgid {
             kill(Pid::from_raw(-(shell_pgid.as_raw())), nix::sys::signal::SIGTTIN).unwrap();
@@ -38,15 +103,35 @@ fn main() {
             libc::signal(libc::SIGTTIN,libc::SIG_IGN);
             libc::signal(libc::SIGTTOU,libc::SIG_IGN);
         }
-        // put mumsh in her own process group
-        shell_pgid = getpid();
-        match setpgid(shell_pgid, shell_pgid) {
-            Ok(_) => {},
+        // children changing state wake the read loop through a pipe
+        let sigchld_fd = match pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC) {
+            Ok((read_end, write_end)) => {
+                SIGCHLD_FD.store(write_end, Ordering::Relaxed);
+                read_end
+            },
             Err(e) => {
-                eprintln!("setpgid: {}", e);
+                eprintln!("pipe: {}", e);
                 return;
             }
         };
+        let action = SigAction::new(SigHandler::Handler(on_sigchld), SaFlags::SA_RESTART, SigSet::empty());
+        if let Err(e) = unsafe { sigaction(Signal::SIGCHLD, &action) } {
+            eprintln!("sigaction: {}", e);
+            return;
+        }
+        // put mumsh in her own process group, unless she is already a group leader
+        shell_pgid = getpid();
+        if getpgrp() != shell_pgid {
+            match setpgid(shell_pgid, shell_pgid) {
+                Ok(_) => {},
+                Err(e) => {
+                    eprintln!("setpgid: {}", e);
+                    return;
+                }
+            };
+        }
+        // a copy of the terminal far from the fds redirections use, like bash does
+        sh.tty_fd = fcntl(1, FcntlArg::F_DUPFD_CLOEXEC(255)).unwrap_or(1);
         sh.set_foreground_pg(shell_pgid.as_raw());
         let reader = match Interface::new("mumsh") {
             Ok(x) => x,
@@ -55,7 +140,6 @@ fn main() {
                 return;
             }
         };
-        reader.define_function("input-check", Arc::new(input::InputCheck));
         reader.bind_sequence("\r", Command::from_str("input-check"));
 
         let mut prompt = " mumsh $ ".on_truecolor(10, 122, 60).truecolor(255, 255, 255).bold().to_string();
@@ -65,38 +149,48 @@ fn main() {
         let mut attr = Termios::from_fd(0).unwrap();
         attr.c_lflag &= !ECHOCTL;
         tcsetattr(0, TCSANOW, &mut attr).unwrap();
+        sh.tty_modes = Some(attr);
 
         loop {
             match reader.set_prompt(&prompt) {
                 Ok(_) => {},
                 Err(_) => {eprintln!("linefeed: error setting prompt")},
             }
+            // what jobs did while the last command ran, the pipe also heard of foreground children
+            children_changed(sigchld_fd);
             sh.try_wait_bg_jobs();
-            match reader.read_line() {
+            sh.report_jobs();
+            // redefined each time, shopt may have changed interactive_comments
+            reader.define_function("input-check", Arc::new(input::InputCheck { comments: sh.get_option("interactive_comments") }));
+            let result = loop {
+                // with jobs around, waiting for keys is cut short now and then to look at the pipe
+                let timeout = if sh.jobs.is_empty() { None } else { Some(Duration::from_millis(100)) };
+                match reader.read_line_step(timeout) {
+                    Ok(Some(x)) => break Ok(x),
+                    Ok(None) => {},
+                    Err(e) => break Err(e),
+                };
+                if children_changed(sigchld_fd) {
+                    sh.try_wait_bg_jobs();
+                    // set -b, printed right away above the prompt, which is drawn again after
+                    if sh.get_option("notify") {
+                        let writer = reader.lock_writer_erase();
+                        sh.report_jobs();
+                        drop(writer);
+                    }
+                }
+            };
+            match result {
                 Ok(ReadResult::Input(mut line)) => {
                     line = input::remove_multiline_prompt(&line);
-                    if line.trim() == "exit" {
-                        println!("bye~");
-                        return;
-                    }
                     executor::run(&line, &mut sh);
-                    // TODO: try wait 1000 times...
-                    let mut i = 0;
-                    loop {
-                        sh.try_wait_bg_jobs();
-                        i += 1;
-                        if i == 1000 {
-                            break;
-                        }
-                    }
-
                 },
                 Ok(ReadResult::Signal(_)) => {
                     println!("received signal");
                 },
                 Ok(ReadResult::Eof) => {
                     println!("bye~");
-                    break;
+                    process::exit(sh.last_status);
                 },
                 Err(e) => {
                     eprintln!("\nmumsh: parse error near `{}\'", e);
@@ -1,17 +1,47 @@
 use std::collections::HashMap;
+use std::env;
+use std::rc::Rc;
+use std::time::Instant;
 
-use nix::unistd::{tcsetpgrp, Pid};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::{tcsetpgrp, getpid, getpgrp, Pid};
 use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use termios::{tcsetattr, Termios, TCSADRAIN};
 
+use crate::parser::ast::Command;
 use crate::types::JobStatus;
 use crate::{common, types::{self, Job}};
 
+/// a value of a variable, if set, and whether it is exported
+pub type SavedVar = (Option<String>, bool);
+
 #[derive(Debug)]
 pub struct Mumsh {
     pub fg_pgid: i32,
     pub current_dir: String,
     pub prev_dir: String,
-    pub jobs: HashMap<i32, types::Job>  // key: pgid | value: Job
+    pub jobs: HashMap<i32, types::Job>, // key: pgid | value: Job
+    pub job_order: Vec<i32>,            // pgids of the jobs, the current one %+ last, the previous one %- before it
+    pub disowned: Vec<i32>,             // pids of disowned jobs, still to be reaped
+    pub is_interactive: bool,           // job control and tty handling only when interactive
+    pub tty_fd: i32,                    // the terminal, kept apart from fds that get redirected
+    pub tty_modes: Option<Termios>,     // the terminal modes of mumsh, given back when a job leaves the foreground
+    pub args: Vec<String>,              // positional parameters, $0 first
+    pub last_status: i32,               // exit status of the last commandline, $?
+    pub pid: i32,                       // pid of mumsh, $$
+    pub last_bg_pid: i32,               // pid of the last background command, $!
+    pub vars: HashMap<String, String>,  // shell variables, exported ones live in the environment
+    pub subst_status: Option<i32>,      // status of the last command substitution, for NAME=$(cmd)
+    pub options: HashMap<String, bool>, // shell options set by shopt, key: name | value: on or off
+    pub set_options: HashMap<String, bool>, // shell options set by set -o
+    pub loop_depth: i32,                // number of loops being run
+    pub breaking: i32,                  // number of loops left to break out of
+    pub continuing: i32,                // loops left to break out of, the last one continues
+    pub functions: HashMap<String, Rc<Command>>,    // key: name | value: body
+    pub local_scopes: Vec<HashMap<String, SavedVar>>,  // one per function being run, key: name | value: previous value
+    pub returning: bool,                // return was run, the function ends
+    pub proc_subst_fds: Vec<i32>,       // fds of <(cmd) and >(cmd) for the command being run
+    pub proc_subst_pids: Vec<i32>       // pids of their subshells
 }
 
 impl Mumsh {
@@ -21,11 +51,94 @@ impl Mumsh {
             current_dir: common::get_current_dir(),
             prev_dir: String::new(),
             jobs: HashMap::new(),
+            job_order: Vec::new(),
+            disowned: Vec::new(),
+            is_interactive: false,
+            tty_fd: 1,
+            tty_modes: None,
+            args: vec![String::from("mumsh")],
+            last_status: 0,
+            pid: getpid().as_raw(),
+            last_bg_pid: 0,
+            vars: HashMap::new(),
+            subst_status: None,
+            options: HashMap::from([
+                (String::from("dotglob"), false),
+                (String::from("failglob"), false),
+                (String::from("globstar"), false),
+                (String::from("interactive_comments"), true),
+                (String::from("nullglob"), false),
+            ]),
+            set_options: HashMap::from([
+                (String::from("noclobber"), false),
+                (String::from("notify"), false),
+            ]),
+            loop_depth: 0,
+            breaking: 0,
+            continuing: 0,
+            functions: HashMap::new(),
+            local_scopes: Vec::new(),
+            returning: false,
+            proc_subst_fds: Vec::new(),
+            proc_subst_pids: Vec::new(),
+        }
+    }
+
+    /// look up a variable, shell variables first, then the environment
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        if let Some(x) = self.vars.get(name) {
+            return Some(x.clone());
+        }
+        env::var(name).ok()
+    }
+
+    /// set a variable, it stays exported if it already is
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        if env::var_os(name).is_some() {
+            env::set_var(name, value);
+        } else {
+            self.vars.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// move a variable into the environment, optionally giving it a new value
+    pub fn export_var(&mut self, name: &str, value: Option<&str>) {
+        let value = match value {
+            Some(x) => Some(x.to_string()),
+            None => self.vars.get(name).cloned(),
+        };
+        self.vars.remove(name);
+        if let Some(x) = value {
+            env::set_var(name, x);
         }
     }
 
+    /// the value of a variable and whether it is exported, to be given back by restore_var
+    pub fn save_var(&self, name: &str) -> SavedVar {
+        (self.get_var(name), env::var_os(name).is_some())
+    }
+
+    /// give a variable back the value saved before, unset if it had none
+    pub fn restore_var(&mut self, name: &str, saved: SavedVar) {
+        self.unset_var(name);
+        match saved {
+            (Some(x), true) => self.export_var(name, Some(&x)),
+            (Some(x), false) => self.set_var(name, &x),
+            (None, _) => {}
+        };
+    }
+
+    pub fn get_option(&self, name: &str) -> bool {
+        self.options.get(name).or_else(|| self.set_options.get(name)).copied().unwrap_or(false)
+    }
+
+    pub fn unset_var(&mut self, name: &str) {
+        self.vars.remove(name);
+        env::remove_var(name);
+    }
+
     pub fn set_foreground_pg(self: &mut Self, pgid: i32) -> bool {
-        match tcsetpgrp(1, Pid::from_raw(pgid)) {
+        match tcsetpgrp(self.tty_fd, Pid::from_raw(pgid)) {
             Ok(_) => {
                 self.fg_pgid = pgid;
                 return true
@@ -37,11 +150,34 @@ impl Mumsh {
         }
     }
 
-    pub fn insert_job(self: &mut Self, pgid: i32, pid: i32) {
+    /// take the terminal back from a foreground job, with the modes of mumsh
+    pub fn reclaim_terminal(&mut self) {
+        self.set_foreground_pg(getpgrp().as_raw());
+        if let Some(x) = self.tty_modes.as_ref() {
+            tcsetattr(self.tty_fd, TCSADRAIN, x).ok();
+        }
+    }
+
+    /// a foreground job got stopped, its terminal modes are kept for fg
+    /// eg: "[1]  + suspended  vim foo"
+    pub fn suspend_job(&mut self, pgid: i32) {
+        let tty_modes = Termios::from_fd(self.tty_fd).ok();
+        if let Some(job) = self.jobs.get_mut(&pgid) {
+            job.tty_modes = tty_modes;
+        }
+        self.set_current_job(pgid);
+        self.reclaim_terminal();
+        println!("{}", self.job_line(pgid, false, false));
+    }
+
+    /// add a process to the job of pgid, which is created with its line if it is the first one
+    pub fn insert_job(&mut self, pgid: i32, pid: i32, line: &str, argv: &str) {
         // pgid exists, push pid...
         if let Some(x) = self.jobs.get_mut(&pgid) {
             x.pids.push(pid);
+            x.all_pids.push(pid);
             x.status.insert(pid, JobStatus::Running);
+            x.argvs.insert(pid, argv.to_string());
             return;
         }
         // find the smallest unused job_id...
@@ -66,8 +202,155 @@ impl Mumsh {
             id: job_id_new, 
             pgid: pgid, 
             pids: vec![pid],
-            status: HashMap::from([(pid, JobStatus::Running)]) 
+            all_pids: vec![pid],
+            status: HashMap::from([(pid, JobStatus::Running)]),
+            argvs: HashMap::from([(pid, argv.to_string())]),
+            cpu_times: HashMap::new(),
+            start: Instant::now(),
+            helpers: Vec::new(),
+            line: line.to_string(),
+            tty_modes: None,
+            changed: false
         });
+        self.job_order.push(pgid);
+    }
+
+    pub fn remove_job(&mut self, pgid: i32) -> Option<Job> {
+        self.job_order.retain(|x| *x != pgid);
+        self.jobs.remove(&pgid)
+    }
+
+    /// make a job the current one, %+
+    pub fn set_current_job(&mut self, pgid: i32) {
+        self.job_order.retain(|x| *x != pgid);
+        self.job_order.push(pgid);
+    }
+
+    /// '+' for the current job, '-' for the previous one, ' ' for the others
+    pub fn job_mark(&self, pgid: i32) -> char {
+        match self.job_order.iter().rev().position(|x| *x == pgid) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    /// find the pgid of a job from a job spec
+    /// eg: %1 the first job, %+ %% or % the current one, %- the previous one,
+    /// %name the one whose command starts with name, %?name the one whose command contains name
+    pub fn find_job(&self, spec: &str) -> Result<i32, String> {
+        let no_job = || format!("{}: no such job", spec);
+        let name = match spec.strip_prefix('%') {
+            Some(x) => x,
+            None => return Err(no_job()),
+        };
+        let pgid = match name {
+            "" | "+" | "%" => self.job_order.last(),
+            "-" => self.job_order.iter().rev().nth(1),
+            x if x.chars().all(|y| y.is_ascii_digit()) => {
+                self.jobs.values().find(|y| y.id.to_string() == x).map(|y| &y.pgid)
+            },
+            x => {
+                let found: Vec<&i32> = match x.strip_prefix('?') {
+                    Some(y) => self.job_order.iter().filter(|z| self.jobs[z].line.contains(y)).collect(),
+                    None => self.job_order.iter().filter(|z| self.jobs[z].line.starts_with(x)).collect(),
+                };
+                if found.len() > 1 {
+                    return Err(format!("{}: ambiguous job spec", spec));
+                }
+                found.first().copied()
+            },
+        };
+        pgid.copied().ok_or_else(no_job)
+    }
+
+    /// send a signal to all processes of a job
+    pub fn signal_job(&self, pgid: i32, signal: Signal) -> nix::Result<()> {
+        // only an interactive mumsh gives each job its own process group
+        if self.is_interactive {
+            return kill(Pid::from_raw(-pgid), signal);
+        }
+        match self.jobs.get(&pgid) {
+            Some(job) => job.pids.iter().try_for_each(|x| kill(Pid::from_raw(*x), signal)),
+            None => Err(nix::Error::ESRCH),
+        }
+    }
+
+    /// wait for the processes of a job until they are done, or until it is stopped
+    /// the job is removed once done, returns the status of its last process
+    pub fn wait_job(&mut self, pgid: i32) -> i32 {
+        while let Some(&pid) = self.jobs.get(&pgid).and_then(|x| x.pids.first()) {
+            // with job control the whole group, the stop of any of its processes is seen at once
+            let status = self.wait_pid(pgid, if self.is_interactive { -pgid } else { pid });
+            if self.jobs.get(&pgid).map(|x| x.is_stopped()) == Some(true) {
+                self.set_current_job(pgid);
+                return status;
+            }
+        }
+        let status = self.jobs.get(&pgid)
+            .and_then(|x| x.all_pids.last().and_then(|y| x.status.get(y)))
+            .map_or(0, |x| x.code());
+        self.reap_job(pgid);
+        status
+    }
+
+    /// remove a job whose processes are done, once its process substitutions are done too
+    pub fn reap_job(&mut self, pgid: i32) {
+        if let Some(job) = self.remove_job(pgid) {
+            for pid in job.helpers {
+                while let Err(nix::Error::EINTR) = waitpid(Pid::from_raw(pid), None) {}
+            }
+        }
+    }
+
+    /// a line about a job for listings, eg: "[1]  + running    sleep 100"
+    /// with_pid puts its pgid before the state, with_time the time since it started after it
+    /// eg: "[2]  - running    12m  cargo build --release | tee log"
+    pub fn job_line(&self, pgid: i32, with_pid: bool, with_time: bool) -> String {
+        let job = match self.jobs.get(&pgid) {
+            Some(x) => x,
+            None => return String::new(),
+        };
+        let pid = if with_pid { format!("{} ", pgid) } else { String::new() };
+        let time = if with_time { format!("{:<4} ", common::format_duration(job.start.elapsed())) } else { String::new() };
+        format!("{:<5}{} {}{:<10} {}{}", format!("[{}]", job.id), self.job_mark(pgid), pid, job.state(), time, job.line)
+    }
+
+    /// wait for a process of a job until it is done or stopped, it leaves the job once done
+    /// -pgid waits for any process of the group, a process substitution one too
+    /// returns its status, 128 + the signal if it is stopped
+    pub fn wait_pid(&mut self, pgid: i32, pid: i32) -> i32 {
+        let result = loop {
+            match common::wait4(Pid::from_raw(pid), Some(WaitPidFlag::WUNTRACED)) {
+                Err(nix::Error::EINTR) => continue,
+                x => break x,
+            }
+        };
+        let job = match self.jobs.get_mut(&pgid) {
+            Some(x) => x,
+            None => return 127,
+        };
+        match result {
+            Ok((WaitStatus::Stopped(_, signal), _)) => {
+                // the signal went to the whole process group
+                job.set_status(JobStatus::Stopped);
+                128 + signal as i32
+            },
+            Ok((x, times)) => {
+                let pid = x.pid().map_or(pid, |y| y.as_raw());
+                job.helpers.retain(|y| *y != pid);
+                if job.all_pids.contains(&pid) {
+                    job.pids.retain(|y| *y != pid);
+                    job.status.insert(pid, JobStatus::from(&x));
+                    job.cpu_times.insert(pid, times);
+                }
+                common::wait_status_to_code(&x)
+            },
+            Err(_) => {     // reaped already, all of the group for -pgid
+                job.pids.retain(|y| *y != pid && pid > 0);
+                127
+            }
+        }
     }
 
     pub fn print_job(self: &Self, pgid: i32) {
@@ -80,11 +363,29 @@ impl Mumsh {
         }
     }
 
+    /// print the jobs stopped or done since the last report, the done ones leave the table
+    pub fn report_jobs(&mut self) {
+        let mut pgids: Vec<i32> = self.jobs.keys().copied().collect();
+        pgids.sort_by_key(|x| self.jobs[x].id);
+        for pgid in pgids {
+            let mark = self.job_mark(pgid);
+            if self.jobs[&pgid].is_done() {
+                if let Some(job) = self.remove_job(pgid) {
+                    job.print_status(mark);
+                }
+            } else if self.jobs[&pgid].changed {
+                println!("{}", self.job_line(pgid, false, false));
+                self.jobs.get_mut(&pgid).unwrap().set_reported();
+            }
+        }
+    }
+
+    /// record the state changes of the jobs, without waiting, they are reported by report_jobs
     pub fn try_wait_bg_jobs(self: &mut Self) {
         let options = Some(WaitPidFlag::WUNTRACED |
                                                 WaitPidFlag::WCONTINUED |  
                                                 WaitPidFlag::WNOHANG);
-        let mut finished_jobs = Vec::new();
+        let mut stopped_jobs = Vec::new();
         for (pgid, job) in self.jobs.iter_mut() {
             let mut i: i32 = 0;
             loop {
@@ -93,37 +394,46 @@ impl Mumsh {
                     Some(x) => pid = x,
                     None => break,
                 };
-                match waitpid(Pid::from_raw(*pid), options) {
-                    Ok(WaitStatus::Exited(pid, status)) => {
-                        // println!("pid: {}, status: {}", pid, status);
-                        job.status.insert(pid.as_raw(), JobStatus::Exited(status));
+                match common::wait4(Pid::from_raw(*pid), options) {
+                    Ok((x @ WaitStatus::Exited(..), times)) | Ok((x @ WaitStatus::Signaled(..), times)) => {
+                        job.status.insert(*pid, JobStatus::from(&x));
+                        job.cpu_times.insert(*pid, times);
                         job.pids.remove(i as usize);
                         i -= 1;
-                        if job.pids.is_empty() {
-                            finished_jobs.push(*pgid);
-                        }
                     },
-                    Ok(WaitStatus::Signaled(pid, signal, bool)) => {
-                        println!("{:?} {:?} {:?}", pid, signal, bool);  // TODO
+                    Ok((WaitStatus::Continued(pid), _)) => {
+                        // continued by someone else is reported, after bg it is just running
+                        let pid = pid.as_raw();
+                        if job.status.get(&pid) == Some(&JobStatus::Stopped) {
+                            job.status.insert(pid, JobStatus::Continued);
+                            job.changed = true;
+                        } else {
+                            job.status.insert(pid, JobStatus::Running);
+                        }
                     },
-                    Ok(WaitStatus::StillAlive) => {},
-                    Ok(WaitStatus::Stopped(pid, signal)) => {
-                        if signal.as_str() == "SIGTTIN" {
-                            println!("suspended (tty input)");  // TODO format output?
+                    Ok((WaitStatus::Stopped(pid, _), _)) => {
+                        // a stop seen already, eg: by a foreground wait for another process of the job
+                        if job.status.insert(pid.as_raw(), JobStatus::Stopped) != Some(JobStatus::Stopped) {
+                            job.changed = true;
+                            stopped_jobs.push(*pgid);
                         }
-                        job.status.insert(pid.as_raw(), JobStatus::Stopped);
                     },
-                    Ok(x) => {println!("{:#?}", x)},    // TODO other situations?
-                    Err(_) => {eprintln!("ERROR WAITPID!")},
+                    // no change, ptrace events only come to tracers
+                    Ok((WaitStatus::StillAlive, _)) | Ok((WaitStatus::PtraceEvent(..), _)) | Ok((WaitStatus::PtraceSyscall(_), _)) => {},
+                    Err(_) => {
+                        // reaped elsewhere, eg: by the wait builtin
+                        job.pids.remove(i as usize);
+                        i -= 1;
+                    },
                 }
                 i += 1;
             }
+            // the job is done once its process substitutions are done too
+            job.helpers.retain(|x| matches!(waitpid(Pid::from_raw(*x), Some(WaitPidFlag::WNOHANG)), Ok(WaitStatus::StillAlive)));
         }
-        for pgid in finished_jobs {
-            if let Some(job) = self.jobs.get(&pgid) {
-                job.print_status();
-            }
-            self.jobs.remove(&pgid);
+        for pgid in stopped_jobs {
+            self.set_current_job(pgid);
         }
+        self.disowned.retain(|x| matches!(waitpid(Pid::from_raw(*x), Some(WaitPidFlag::WNOHANG)), Ok(WaitStatus::StillAlive)));
     }
 }
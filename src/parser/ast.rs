@@ -0,0 +1,185 @@
+use std::rc::Rc;
+
+use crate::expand::param;
+use crate::types::Token;
+
+/// commands separated by ; & or newlines
+/// eg: "a && b & c" => [(a && b, background), (c, foreground)]
+#[derive(Debug, Clone)]
+pub struct List {
+    pub items: Vec<ListItem>
+}
+
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    pub is_background: bool
+}
+
+/// pipelines joined by && and ||, eg: "a | b && c"
+#[derive(Debug, Clone)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(AndOrOp, Pipeline)>
+}
+
+impl AndOr {
+    /// the source of the pipelines joined by their operators
+    pub fn text(&self) -> String {
+        let mut text = self.first.text.clone();
+        for (op, pipeline) in self.rest.iter() {
+            text.push_str(if *op == AndOrOp::And { " && " } else { " || " });
+            text.push_str(&pipeline.text);
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AndOrOp {
+    And,
+    Or
+}
+
+/// commands joined by |, text is the source it was parsed from
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub cmds: Vec<Command>,
+    pub text: String
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirect>),
+    FunctionDef(String, Rc<Command>)        // name, body: a compound command with its redirections
+}
+
+#[derive(Debug, Clone)]
+pub struct SimpleCommand {
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>
+}
+
+#[derive(Debug, Clone)]
+pub enum CompoundCommand {
+    Arith(String),                          // (( expr ))
+    BraceGroup(List),                       // { list; }
+    Subshell(List),                         // ( list ), run in a forked mumsh
+    If(Vec<(List, List)>, Option<List>),    // condition and body of if and each elif, else body
+    While(List, List),                      // condition, body
+    Until(List, List),
+    For(String, Option<Vec<Word>>, List),   // name, words, "$@" if None, body
+    Case(Word, Vec<CaseItem>)
+}
+
+/// pat1 | pat2) body ;;
+#[derive(Debug, Clone)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List
+}
+
+/// a redirection, eg: "2>&1" => { fd: Some(2), op: ">&", word: "1" }
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: Option<i32>,            // the io number before the operator
+    pub fd_var: Option<String>,     // the name in {name}> before the operator
+    pub op: String,                 // < << <<- <<< > >> >| <> &> &>> >& <&
+    pub word: Word,                 // file, fd, heredoc delimiter or here-string
+    pub here_doc: String            // body of << and <<-
+}
+
+/// a word made of quoted and unquoted parts, eg: a"b c"'d' => [a, "b c", 'd']
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word {
+    pub parts: Vec<WordPart>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordPart {
+    Unquoted(String),       // may hold $ expansions, backticks, globs and braces
+    SingleQuoted(String),
+    DoubleQuoted(String)    // may hold $ expansions and backticks
+}
+
+impl Word {
+    /// the word as written in the source, quotes included
+    /// $'...' is given decoded in single quotes
+    pub fn raw(&self) -> String {
+        let mut string = String::new();
+        for part in self.parts.iter() {
+            match part {
+                WordPart::Unquoted(x) => string.push_str(x),
+                WordPart::SingleQuoted(x) => string.push_str(&format!("\'{}\'", x.replace('\'', "\'\\\'\'"))),
+                WordPart::DoubleQuoted(x) => string.push_str(&format!("\"{}\"", x)),
+            };
+        }
+        string
+    }
+
+    /// the word with its quotes and backslashes removed, nothing is expanded
+    pub fn unquoted(&self) -> String {
+        let mut string = String::new();
+        for part in self.parts.iter() {
+            match part {
+                WordPart::Unquoted(x) => string.push_str(&remove_backslashes(x, "")),
+                WordPart::SingleQuoted(x) => string.push_str(x),
+                WordPart::DoubleQuoted(x) => string.push_str(&remove_backslashes(x, "$`\"\\\n")),
+            };
+        }
+        string
+    }
+
+    /// whether any part of the word is quoted or escaped
+    pub fn is_quoted(&self) -> bool {
+        self.parts.iter().any(|x| match x {
+            WordPart::Unquoted(y) => y.contains('\\'),
+            _ => true,
+        })
+    }
+
+    /// whether the word is the unquoted reserved word keyword
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.parts.as_slice(), [WordPart::Unquoted(x)] if x == keyword)
+    }
+
+    /// whether the word is an assignment, NAME=value with the NAME unquoted
+    pub fn is_assignment(&self) -> bool {
+        match self.parts.first() {
+            Some(WordPart::Unquoted(x)) => matches!(x.split_once('='), Some((name, _)) if param::is_name(name)),
+            _ => false,
+        }
+    }
+
+    /// the token handed to the expansion, a word quoted as a whole keeps its quote kind
+    /// eg: 'a b' => ("'", "a b"), a"b" => ("", "a\"b\"")
+    /// other words go back to source text that expand_text splits into its parts again,
+    /// so raw has to quote each part the way expand_text reads it, $'...' included
+    pub fn to_token(&self) -> Token {
+        match self.parts.as_slice() {
+            [WordPart::SingleQuoted(x)] => (String::from("\'"), x.clone()),
+            [WordPart::DoubleQuoted(x)] => (String::from("\""), x.clone()),
+            _ => (String::new(), self.raw()),
+        }
+    }
+}
+
+/// remove the backslashes that escape a character, only the ones in escapable if not empty
+fn remove_backslashes(text: &str, escapable: &str) -> String {
+    let mut string = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&x) = chars.peek() {
+                if escapable.is_empty() || escapable.contains(x) {
+                    string.push(x);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        string.push(c);
+    }
+    string
+}
@@ -0,0 +1,548 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::expand::{find_closing, param};
+use super::ast::{Word, WordPart};
+use super::{ParseError, Parser};
+
+/// operators, longer ones first so that the longest match wins
+const OPERATORS: [&str; 20] = [
+    "<<<", "<<-", "&>>", "&&", "||", ";;", "<<", ">>", ">&", "<&", ">|", "<>", "&>",
+    ";", "&", "|", "(", ")", "<", ">",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(Word),
+    IoNumber(i32),          // digits right before < or >, eg: the 2 in 2>&1
+    IoVar(String),          // {name} right before < or >, the fd is allocated and stored in name
+    Op(String),             // control and redirection operators, newline included
+    Eof
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(x) => write!(f, "{}", x.raw()),
+            Token::IoNumber(x) => write!(f, "{}", x),
+            Token::IoVar(x) => write!(f, "{{{}}}", x),
+            Token::Op(x) if x == "\n" => write!(f, "newline"),
+            Token::Op(x) => write!(f, "{}", x),
+            Token::Eof => write!(f, "newline"),
+        }
+    }
+}
+
+pub struct Lexer<'a> {
+    chars: Cow<'a, [char]>,         // borrowed by the lexers of $( ... ) inside
+    pub pos: usize,
+    here_doc_end: Option<usize>,    // where to go on at the next newline, after the heredoc bodies read
+    at_eof: bool,                   // no more input will follow, heredocs may end at the end of input
+    comments: bool,                 // whether # starts a comment
+    input: Option<&'a mut dyn Iterator<Item = String>>     // lines appended to chars when they run out
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(text: &str, at_eof: bool, comments: bool) -> Lexer<'a> {
+        Lexer { chars: Cow::Owned(text.chars().collect()), pos: 0, here_doc_end: None, at_eof, comments, input: None }
+    }
+
+    /// a lexer that reads the next line of input whenever it needs more text
+    /// eg: for a function body only the lines up to its } are read
+    pub fn with_input(input: &'a mut dyn Iterator<Item = String>, comments: bool) -> Lexer<'a> {
+        Lexer { chars: Cow::Owned(Vec::new()), pos: 0, here_doc_end: None, at_eof: false, comments, input: Some(input) }
+    }
+
+    /// append the next line of input to the text, or set at_eof once the input is used up
+    /// false if neither happened, scanning again would give the same result then
+    fn read_more(&mut self) -> bool {
+        let input = match self.input.as_mut() {
+            Some(x) if !self.at_eof => x,
+            _ => return false,
+        };
+        match input.next() {
+            Some(line) => {
+                let chars = self.chars.to_mut();
+                chars.extend(line.chars());
+                chars.push('\n');
+                true
+            },
+            None => {
+                self.at_eof = true;
+                true
+            },
+        }
+    }
+
+    /// the source text between start and end
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.chars[start..end.min(self.chars.len())].iter().collect()
+    }
+
+    /// lex the next token, returns it with its start and end position
+    /// a token that runs past the end of the text is read again once more input is there
+    pub fn next_token(&mut self) -> Result<(Token, usize, usize), ParseError> {
+        let start = self.pos;
+        loop {
+            match self.scan_token() {
+                Ok((Token::Eof, _, _)) | Err(ParseError::Incomplete(_)) if self.read_more() => self.pos = start,
+                x => return x,
+            };
+        }
+    }
+
+    fn scan_token(&mut self) -> Result<(Token, usize, usize), ParseError> {
+        loop {
+            match self.chars.get(self.pos) {
+                Some(' ') | Some('\t') => self.pos += 1,
+                Some('\\') if self.is_continuation(self.pos)? => self.pos += 2,
+                Some('#') if self.comments => {
+                    // a comment runs up to the end of the line, the newline stays
+                    self.pos = self.find_char(self.pos, '\n').unwrap_or(self.chars.len());
+                },
+                _ => break,
+            };
+        }
+        let start = self.pos;
+        let c = match self.chars.get(self.pos) {
+            Some(x) => *x,
+            None => return Ok((Token::Eof, start, start)),
+        };
+        if c == '\n' {
+            self.pos += 1;
+            // skip over the heredoc bodies that start on this line
+            if let Some(x) = self.here_doc_end.take() {
+                self.pos = x;
+            }
+            return Ok((Token::Op(String::from("\n")), start, start + 1));
+        }
+        for op in OPERATORS.iter() {
+            if self.starts_with(op) && !self.is_process_subst(self.pos) {
+                self.pos += op.chars().count();
+                return Ok((Token::Op(op.to_string()), start, self.pos));
+            }
+        }
+        let word = self.read_word()?;
+        if let [WordPart::Unquoted(x)] = word.parts.as_slice() {
+            if matches!(self.chars.get(self.pos), Some('<') | Some('>')) {
+                if x.chars().all(|y| y.is_ascii_digit()) {
+                    if let Ok(n) = x.parse() {
+                        return Ok((Token::IoNumber(n), start, self.pos));
+                    }
+                }
+                if let Some(name) = x.strip_prefix('{').and_then(|y| y.strip_suffix('}')) {
+                    if param::is_name(name) {
+                        return Ok((Token::IoVar(name.to_string()), start, self.pos));
+                    }
+                }
+            }
+        }
+        Ok((Token::Word(word), start, self.pos))
+    }
+
+    /// whether the backslash at i is followed by a newline, the two of them are then removed
+    /// a backslash at the very end asks for more input, unless there is no more
+    fn is_continuation(&self, i: usize) -> Result<bool, ParseError> {
+        match self.chars.get(i + 1) {
+            Some('\n') if i + 2 == self.chars.len() && !self.at_eof => Err(ParseError::Incomplete(String::new())),
+            Some('\n') => Ok(true),
+            None if !self.at_eof => Err(ParseError::Incomplete(String::new())),
+            _ => Ok(false),
+        }
+    }
+
+    fn starts_with(&self, string: &str) -> bool {
+        string.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    /// read a word up to the next unquoted blank or operator
+    fn read_word(&mut self) -> Result<Word, ParseError> {
+        let mut word = Word::default();
+        let mut unquoted = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if " \t\n;&|<>()".contains(c) && !self.is_process_subst(self.pos) {
+                break;
+            }
+            let end = match c {
+                '\'' | '\"' => {
+                    if !unquoted.is_empty() {
+                        word.parts.push(WordPart::Unquoted(unquoted.clone()));
+                        unquoted.clear();
+                    }
+                    let end = if c == '\'' {
+                        self.find_char(self.pos + 1, '\'').ok_or_else(|| ParseError::Incomplete(String::from("quote")))?
+                    } else {
+                        self.scan_double_quoted(self.pos + 1)?
+                    };
+                    let inner = self.slice(self.pos + 1, end);
+                    word.parts.push(if c == '\'' { WordPart::SingleQuoted(inner) } else { WordPart::DoubleQuoted(inner) });
+                    self.pos = end + 1;
+                    continue;
+                },
+                '$' if self.chars.get(self.pos + 1) == Some(&'\'') => {
+                    if !unquoted.is_empty() {
+                        word.parts.push(WordPart::Unquoted(unquoted.clone()));
+                        unquoted.clear();
+                    }
+                    let (string, end) = read_ansi_c(&self.chars, self.pos + 2)
+                        .ok_or_else(|| ParseError::Incomplete(String::from("quote")))?;
+                    word.parts.push(WordPart::SingleQuoted(string));
+                    self.pos = end + 1;
+                    continue;
+                },
+                '\\' if self.is_continuation(self.pos)? => {
+                    self.pos += 2;
+                    continue;
+                },
+                '\\' => (self.pos + 1).min(self.chars.len() - 1),
+                '`' => self.scan_backtick(self.pos + 1)?,
+                '$' => self.scan_dollar(self.pos)?,
+                '<' | '>' => self.scan_command_subst(self.pos + 2)?,
+                _ => self.pos,
+            };
+            unquoted.push_str(&self.slice(self.pos, end + 1));
+            self.pos = end + 1;
+        }
+        if !unquoted.is_empty() {
+            word.parts.push(WordPart::Unquoted(unquoted));
+        }
+        Ok(word)
+    }
+
+    /// whether a process substitution starts at i, eg: <(cmd), >(cmd)
+    fn is_process_subst(&self, i: usize) -> bool {
+        matches!(self.chars.get(i), Some('<') | Some('>')) && self.chars.get(i + 1) == Some(&'(')
+    }
+
+    fn find_char(&self, start: usize, c: char) -> Option<usize> {
+        self.chars.iter().skip(start).position(|&x| x == c).map(|x| x + start)
+    }
+
+    /// find the " that closes a double quoted string starting at start
+    fn scan_double_quoted(&mut self, start: usize) -> Result<usize, ParseError> {
+        let mut i = start;
+        while i < self.chars.len() {
+            match self.chars[i] {
+                '\\' => i += 1,
+                '\"' => return Ok(i),
+                '`' => i = self.scan_backtick(i + 1)?,
+                '$' => i = self.scan_dollar(i)?,
+                _ => {},
+            };
+            i += 1;
+        }
+        Err(ParseError::Incomplete(String::from("dquote")))
+    }
+
+    /// find the ` that closes a command substitution starting at start
+    fn scan_backtick(&self, start: usize) -> Result<usize, ParseError> {
+        let mut i = start;
+        while i < self.chars.len() {
+            match self.chars[i] {
+                '\\' => i += 1,
+                '`' => return Ok(i),
+                _ => {},
+            };
+            i += 1;
+        }
+        Err(ParseError::Incomplete(String::from("bquote")))
+    }
+
+    /// find the end of the expansion that starts with the $ at start
+    /// eg: ${...}, $(...), $((...)), a lonely $ ends right where it is
+    fn scan_dollar(&mut self, start: usize) -> Result<usize, ParseError> {
+        match self.chars.get(start + 1) {
+            Some('{') => find_closing(&self.chars, start + 2, '{', '}')
+                .ok_or_else(|| ParseError::Incomplete(String::from("braceparam"))),
+            Some('(') if self.chars.get(start + 2) == Some(&'(') => {
+                match find_closing(&self.chars, start + 3, '(', ')') {
+                    Some(x) if self.chars.get(x + 1) == Some(&')') => Ok(x + 1),
+                    Some(_) => self.scan_command_subst(start + 2),
+                    None => Err(ParseError::Incomplete(String::from("mathsubst"))),
+                }
+            },
+            Some('(') => self.scan_command_subst(start + 2),
+            _ => Ok(start),
+        }
+    }
+
+    /// parse the commands of a $( ... ) starting at start, returns the position of its )
+    fn scan_command_subst(&self, start: usize) -> Result<usize, ParseError> {
+        find_command_subst_end(&self.chars, start, self.at_eof, self.comments)
+    }
+
+    /// take the () that follows the name in a function definition, eg: "f ()", "f()"
+    /// nothing is taken if they are not there
+    pub fn read_function_parens(&mut self) -> bool {
+        let mut i = self.pos;
+        let mut expected = vec![')', '('];
+        while let Some(&c) = self.chars.get(i) {
+            if c == ' ' || c == '\t' {
+                i += 1;
+            } else if Some(&c) == expected.last() {
+                expected.pop();
+                i += 1;
+                if expected.is_empty() {
+                    self.pos = i;
+                    return true;
+                }
+            } else {
+                break;
+            }
+        }
+        false
+    }
+
+    /// read the expression of (( ... )) right after its first (
+    /// returns None if the parentheses do not close with ))
+    pub fn read_arith_command(&mut self) -> Result<Option<String>, ParseError> {
+        if self.chars.get(self.pos) != Some(&'(') {
+            return Ok(None);
+        }
+        loop {
+            match find_closing(&self.chars, self.pos + 1, '(', ')') {
+                Some(x) if self.chars.get(x + 1) == Some(&')') => {
+                    let expr = self.slice(self.pos + 1, x);
+                    self.pos = x + 2;
+                    return Ok(Some(expr));
+                },
+                Some(_) => return Ok(None),
+                None if self.read_more() => {},
+                None => return Err(ParseError::Incomplete(String::from("math"))),
+            };
+        }
+    }
+
+    /// read the body of a heredoc, it starts on the line after the current one,
+    /// or right after the previous heredoc of the same line
+    pub fn read_here_doc(&mut self, delimiter: &str, strip_tabs: bool) -> Result<String, ParseError> {
+        let mut i = match self.here_doc_end {
+            Some(x) => x,
+            None => loop {
+                match self.find_line_end(self.pos) {
+                    Some(x) => break x + 1,
+                    None if self.read_more() => {},
+                    None => break self.chars.len(),
+                };
+            },
+        };
+        let mut body = String::new();
+        loop {
+            if i >= self.chars.len() {
+                if self.read_more() {
+                    continue;
+                }
+                break;
+            }
+            let end = self.find_char(i, '\n').unwrap_or(self.chars.len());
+            let line = self.slice(i, end);
+            let line = if strip_tabs { line.trim_start_matches('\t') } else { line.as_str() };
+            if line == delimiter {
+                self.here_doc_end = Some((end + 1).min(self.chars.len()));
+                return Ok(body);
+            }
+            body.push_str(line);
+            body.push('\n');
+            i = end + 1;
+        }
+        if !self.at_eof {
+            return Err(ParseError::Incomplete(String::from("heredoc")));
+        }
+        eprintln!("mumsh: warning: here-document delimited by end-of-file (wanted `{}')", delimiter);
+        self.here_doc_end = Some(self.chars.len());
+        Ok(body)
+    }
+
+    /// find the newline that ends the current line, skipping over quotes
+    fn find_line_end(&mut self, start: usize) -> Option<usize> {
+        let mut i = start;
+        while i < self.chars.len() {
+            match self.chars[i] {
+                '\n' => return Some(i),
+                '#' if self.comments && (i == 0 || " \t;&|<>()".contains(self.chars[i - 1])) => {
+                    return self.find_char(i, '\n');
+                },
+                '\\' => i += 1,
+                '$' if self.chars.get(i + 1) == Some(&'\'') => i = read_ansi_c(&self.chars, i + 2)?.1,
+                '\'' => i = self.find_char(i + 1, '\'')?,
+                '\"' => i = self.scan_double_quoted(i + 1).ok()?,
+                '`' => i = self.scan_backtick(i + 1).ok()?,
+                _ => {},
+            };
+            i += 1;
+        }
+        None
+    }
+}
+
+/// parse the commands of a $( ... ), <( ... ) or >( ... ) starting at start, returns the position of its )
+/// eg: "case a in a) echo y;; esac) b" => 26, the ) of the case pattern does not end it
+pub fn find_command_subst_end(chars: &[char], start: usize, at_eof: bool, comments: bool) -> Result<usize, ParseError> {
+    let lexer = Lexer { chars: Cow::Borrowed(chars), pos: start, here_doc_end: None, at_eof, comments, input: None };
+    let mut parser = Parser::new(lexer);
+    parser.parse_list()?;
+    match parser.next()? {
+        Token::Op(x) if x == ")" => Ok(parser.lexer.pos - 1),
+        Token::Eof => Err(ParseError::Incomplete(String::from("cmdsubst"))),
+        x => Err(ParseError::Syntax(x.to_string())),
+    }
+}
+
+/// read the inside of $'...' starting at start, with its backslash escapes decoded
+/// returns the string and the position of the closing '
+/// eg: "a\tb\x41\u263a'" => ("a<tab>bA\u{263a}", 11)
+pub fn read_ansi_c(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut string = String::new();
+    let mut i = start;
+    loop {
+        let c = *chars.get(i)?;
+        if c == '\'' {
+            return Some((string, i));
+        }
+        i += 1;
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        let x = *chars.get(i)?;
+        i += 1;
+        let decoded = match x {
+            'a' => '\x07',
+            'b' => '\x08',
+            'e' | 'E' => '\x1b',
+            'f' => '\x0c',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\x0b',
+            'c' => {
+                // control character, eg: \cA
+                let y = *chars.get(i)?;
+                i += 1;
+                ((y.to_ascii_uppercase() as u8) ^ 0x40) as char
+            },
+            '0'..='7' => {
+                let mut value = x.to_digit(8).unwrap();
+                let mut len = 1;
+                while len < 3 {
+                    match chars.get(i).and_then(|y| y.to_digit(8)) {
+                        Some(y) => value = value * 8 + y,
+                        None => break,
+                    };
+                    i += 1;
+                    len += 1;
+                }
+                char::from_u32(value & 0xff).unwrap_or('\u{fffd}')
+            },
+            'x' | 'u' | 'U' => {
+                let max_len = match x { 'x' => 2, 'u' => 4, _ => 8 };
+                let mut value = 0;
+                let mut len = 0;
+                while len < max_len {
+                    match chars.get(i).and_then(|y| y.to_digit(16)) {
+                        Some(y) => value = value * 16 + y,
+                        None => break,
+                    };
+                    i += 1;
+                    len += 1;
+                }
+                if len == 0 {       // no digits, the escape stays as it is
+                    string.push('\\');
+                    x
+                } else {
+                    char::from_u32(value).unwrap_or('\u{fffd}')
+                }
+            },
+            '\\' | '\'' | '\"' | '?' => x,
+            _ => {
+                string.push('\\');
+                x
+            },
+        };
+        string.push(decoded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// all the tokens of text, which is complete
+    fn tokens(text: &str) -> Result<Vec<Token>, ParseError> {
+        let mut lexer = Lexer::new(text, true, true);
+        let mut result = Vec::new();
+        loop {
+            match lexer.next_token()?.0 {
+                Token::Eof => return Ok(result),
+                x => result.push(x),
+            };
+        }
+    }
+
+    fn word(parts: Vec<WordPart>) -> Token {
+        Token::Word(Word { parts })
+    }
+
+    fn unquoted(text: &str) -> WordPart {
+        WordPart::Unquoted(text.to_string())
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(tokens("a&&b;c 2>&1"), Ok(vec![
+            word(vec![unquoted("a")]), Token::Op(String::from("&&")), word(vec![unquoted("b")]),
+            Token::Op(String::from(";")), word(vec![unquoted("c")]), Token::IoNumber(2),
+            Token::Op(String::from(">&")), word(vec![unquoted("1")]),
+        ]));
+        assert_eq!(tokens("{fd}>f"), Ok(vec![
+            Token::IoVar(String::from("fd")), Token::Op(String::from(">")), word(vec![unquoted("f")]),
+        ]));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(tokens("a'b c'\"d $e\"f"), Ok(vec![word(vec![
+            unquoted("a"), WordPart::SingleQuoted(String::from("b c")),
+            WordPart::DoubleQuoted(String::from("d $e")), unquoted("f"),
+        ])]));
+        assert_eq!(tokens("$'a\\tb' a\\ b"), Ok(vec![
+            word(vec![WordPart::SingleQuoted(String::from("a\tb"))]), word(vec![unquoted("a\\ b")]),
+        ]));
+        // operators inside quotes and substitutions do not end the word
+        assert_eq!(tokens("\"a;b\" $(c | d) `e;f` <(g)"), Ok(vec![
+            word(vec![WordPart::DoubleQuoted(String::from("a;b"))]), word(vec![unquoted("$(c | d)")]),
+            word(vec![unquoted("`e;f`")]), word(vec![unquoted("<(g)")]),
+        ]));
+        assert_eq!(tokens("$(case a in a) b;; esac)"), Ok(vec![word(vec![unquoted("$(case a in a) b;; esac)")])]));
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(tokens("a #b\nc#d"), Ok(vec![
+            word(vec![unquoted("a")]), Token::Op(String::from("\n")), word(vec![unquoted("c#d")]),
+        ]));
+        let mut lexer = Lexer::new("a #b", true, false);
+        assert_eq!(lexer.next_token().map(|x| x.0), Ok(word(vec![unquoted("a")])));
+        assert_eq!(lexer.next_token().map(|x| x.0), Ok(word(vec![unquoted("#b")])));
+    }
+
+    #[test]
+    fn incomplete() {
+        let incomplete = |text: &str| Lexer::new(text, false, true).next_token().err();
+        assert_eq!(incomplete("'a"), Some(ParseError::Incomplete(String::from("quote"))));
+        assert_eq!(incomplete("\"a"), Some(ParseError::Incomplete(String::from("dquote"))));
+        assert_eq!(incomplete("`a"), Some(ParseError::Incomplete(String::from("bquote"))));
+        assert_eq!(incomplete("${a"), Some(ParseError::Incomplete(String::from("braceparam"))));
+        assert_eq!(incomplete("$(a"), Some(ParseError::Incomplete(String::from("cmdsubst"))));
+        assert_eq!(incomplete("a\\"), Some(ParseError::Incomplete(String::new())));
+        // at the end of input a trailing backslash is literal
+        assert_eq!(tokens("a\\"), Ok(vec![word(vec![unquoted("a\\")])]));
+    }
+
+    #[test]
+    fn ansi_c() {
+        let chars: Vec<char> = "a\\x41\\101\\u263a\\cA\\q'".chars().collect();
+        assert_eq!(read_ansi_c(&chars, 0), Some((String::from("aAA\u{263a}\x01\\q"), 20)));
+        assert_eq!(read_ansi_c(&['a'], 0), None);
+    }
+}
@@ -1 +1,588 @@
-pub mod parse_line;
+pub mod ast;
+pub mod lexer;
+
+use std::rc::Rc;
+
+use ast::{AndOr, AndOrOp, CaseItem, Command, CompoundCommand, List, ListItem, Pipeline, Redirect, SimpleCommand, Word};
+use lexer::{Lexer, Token};
+use crate::expand::param;
+
+const REDIRECT_OPERATORS: [&str; 12] = ["<", "<<", "<<-", "<<<", ">", ">>", ">|", "<>", "&>", "&>>", ">&", "<&"];
+
+/// reserved words that end a list, eg: the then in "if a; then"
+const LIST_TERMINATORS: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+/// reserved words that start a compound command
+const COMPOUND_KEYWORDS: [&str; 6] = ["{", "if", "while", "until", "for", "case"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Incomplete(String),     // the input ends inside a construct, eg: "dquote", "heredoc", "cmdand"
+    Syntax(String)          // the unexpected token
+}
+
+/// parse a whole program
+/// with at_eof, no more input will follow, so heredocs may end at the end of input
+/// with comments, # at the start of a word starts a comment
+/// eg: "a | b && c &" => List [AndOr (Pipeline [a, b]) && (Pipeline [c]), background]
+pub fn parse(text: &str, at_eof: bool, comments: bool) -> Result<List, ParseError> {
+    let mut parser = Parser::new(Lexer::new(text, at_eof, comments));
+    let list = parser.parse_list()?;
+    match parser.next()? {
+        Token::Eof => Ok(list),
+        x => Err(ParseError::Syntax(x.to_string())),
+    }
+}
+
+/// a parser for the commands of a script, that reads its lines from input as they are needed
+/// eg: lines of a file, or of stdin so that the commands run can read the lines after them
+pub fn script_parser(input: &mut dyn Iterator<Item = String>) -> Parser<'_> {
+    Parser::new(Lexer::with_input(input, true))
+}
+
+/// recursive descent parser, one token of lookahead
+pub struct Parser<'a> {
+    pub lexer: Lexer<'a>,
+    peeked: Option<(Token, usize, usize)>,  // token, start, end
+    last_end: usize                         // end of the last token taken
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Parser<'a> {
+        Parser { lexer, peeked: None, last_end: 0 }
+    }
+
+    fn peek(&mut self) -> Result<&Token, ParseError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next_token()?);
+        }
+        Ok(&self.peeked.as_ref().unwrap().0)
+    }
+
+    /// start position of the next token
+    fn peek_start(&mut self) -> Result<usize, ParseError> {
+        self.peek()?;
+        Ok(self.peeked.as_ref().unwrap().1)
+    }
+
+    pub fn next(&mut self) -> Result<Token, ParseError> {
+        let (token, _, end) = match self.peeked.take() {
+            Some(x) => x,
+            None => self.lexer.next_token()?,
+        };
+        self.last_end = end;
+        Ok(token)
+    }
+
+    fn peek_op(&mut self, op: &str) -> Result<bool, ParseError> {
+        Ok(matches!(self.peek()?, Token::Op(x) if x == op))
+    }
+
+    /// whether the next token is the reserved word keyword
+    fn peek_keyword(&mut self, keyword: &str) -> Result<bool, ParseError> {
+        Ok(matches!(self.peek()?, Token::Word(x) if x.is_keyword(keyword)))
+    }
+
+    /// take the reserved word keyword, what is the construct it belongs to
+    fn expect_keyword(&mut self, keyword: &str, what: &str) -> Result<(), ParseError> {
+        match self.next()? {
+            Token::Word(x) if x.is_keyword(keyword) => Ok(()),
+            Token::Eof => Err(ParseError::Incomplete(what.to_string())),
+            x => Err(ParseError::Syntax(x.to_string())),
+        }
+    }
+
+    /// a list that may not be empty, what is the construct it belongs to
+    fn parse_nonempty_list(&mut self, what: &str) -> Result<List, ParseError> {
+        let list = self.parse_list()?;
+        if list.items.is_empty() {
+            return match self.next()? {
+                Token::Eof => Err(ParseError::Incomplete(what.to_string())),
+                x => Err(ParseError::Syntax(x.to_string())),
+            };
+        }
+        Ok(list)
+    }
+
+    fn skip_newlines(&mut self) -> Result<(), ParseError> {
+        while self.peek_op("\n")? {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    /// newlines may follow && || and |, but something has to come after them
+    fn skip_newlines_expecting(&mut self, what: &str) -> Result<(), ParseError> {
+        self.skip_newlines()?;
+        if *self.peek()? == Token::Eof {
+            return Err(ParseError::Incomplete(what.to_string()));
+        }
+        Ok(())
+    }
+
+    /// whether the next token ends a list: the end of input, a ) that closes $( ... ), ;; or a reserved word like fi
+    fn is_list_end_next(&mut self) -> Result<bool, ParseError> {
+        if *self.peek()? == Token::Eof || self.peek_op(")")? || self.peek_op(";;")? {
+            return Ok(true);
+        }
+        Ok(matches!(self.peek()?, Token::Word(x) if LIST_TERMINATORS.iter().any(|y| x.is_keyword(y))))
+    }
+
+    /// list: and_or ((; | & | newline) and_or)*
+    /// stops at the end of input, at a ) that closes $( ... ), at ;; or at a reserved word like fi
+    pub fn parse_list(&mut self) -> Result<List, ParseError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines()?;
+            if self.is_list_end_next()? {
+                break;
+            }
+            let and_or = self.parse_and_or()?;
+            let is_background = self.peek_op("&")?;
+            let separated = is_background || self.peek_op(";")? || self.peek_op("\n")?;
+            items.push(ListItem { and_or, is_background });
+            if !separated {
+                break;
+            }
+            self.next()?;
+        }
+        Ok(List { items })
+    }
+
+    /// the and-or lists up to the end of the next line that is not empty, None at the end of input
+    /// nothing after that newline is read, eg: "a; b &\nc" => [(a, foreground), (b, background)]
+    pub fn parse_line(&mut self) -> Result<Option<List>, ParseError> {
+        let mut items = Vec::new();
+        loop {
+            if self.peek_op("\n")? {
+                self.next()?;
+                if items.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            if *self.peek()? == Token::Eof {
+                break;
+            }
+            if self.is_list_end_next()? {
+                return Err(ParseError::Syntax(self.next()?.to_string()));
+            }
+            let and_or = self.parse_and_or()?;
+            let is_background = self.peek_op("&")?;
+            items.push(ListItem { and_or, is_background });
+            if is_background || self.peek_op(";")? {
+                self.next()?;
+            } else if !self.peek_op("\n")? && *self.peek()? != Token::Eof {
+                return Err(ParseError::Syntax(self.next()?.to_string()));
+            }
+        }
+        Ok(if items.is_empty() { None } else { Some(List { items }) })
+    }
+
+    /// and_or: pipeline ((&& | ||) pipeline)*
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let op = if self.peek_op("&&")? {
+                AndOrOp::And
+            } else if self.peek_op("||")? {
+                AndOrOp::Or
+            } else {
+                break;
+            };
+            self.next()?;
+            self.skip_newlines_expecting(if op == AndOrOp::And { "cmdand" } else { "cmdor" })?;
+            rest.push((op, self.parse_pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    /// pipeline: command (| command)*
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let start = self.peek_start()?;
+        let mut cmds = vec![self.parse_command()?];
+        while self.peek_op("|")? {
+            self.next()?;
+            self.skip_newlines_expecting("pipe")?;
+            cmds.push(self.parse_command()?);
+        }
+        let text = self.lexer.slice(start, self.last_end).trim().to_string();
+        Ok(Pipeline { cmds, text })
+    }
+
+    /// command: compound_command redirect* | function_definition | simple_command
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        if self.peek_keyword("function")? {
+            self.next()?;
+            return self.parse_function(true);
+        }
+        if let Token::Word(x) = self.peek()? {
+            if !x.is_quoted() && is_function_name(&x.raw()) && self.lexer.read_function_parens() {
+                return self.parse_function(false);
+            }
+        }
+        let compound = if self.peek_op("(")? {
+            self.next()?;
+            match self.lexer.read_arith_command()? {
+                Some(expr) => {
+                    self.last_end = self.lexer.pos;
+                    CompoundCommand::Arith(expr)
+                },
+                None => self.parse_subshell()?,
+            }
+        } else if self.peek_keyword("{")? {
+            self.parse_brace_group()?
+        } else if self.peek_keyword("if")? {
+            self.parse_if()?
+        } else if self.peek_keyword("while")? || self.peek_keyword("until")? {
+            self.parse_while()?
+        } else if self.peek_keyword("for")? {
+            self.parse_for()?
+        } else if self.peek_keyword("case")? {
+            self.parse_case()?
+        } else {
+            return self.parse_simple_command();
+        };
+        let redirects = self.parse_redirects()?;
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    /// function_definition: name () compound_command | function name [()] compound_command
+    /// the () are already taken when there is no function keyword
+    fn parse_function(&mut self, has_keyword: bool) -> Result<Command, ParseError> {
+        let name = match self.next()? {
+            Token::Word(x) if !x.is_quoted() && is_function_name(&x.raw()) => x.raw(),
+            Token::Eof => return Err(ParseError::Incomplete(String::from("function"))),
+            x => return Err(ParseError::Syntax(x.to_string())),
+        };
+        if has_keyword {
+            self.lexer.read_function_parens();
+        }
+        self.skip_newlines()?;
+        let is_compound = match self.peek()? {
+            Token::Op(x) => x == "(",
+            Token::Word(x) => COMPOUND_KEYWORDS.iter().any(|y| x.is_keyword(y)),
+            _ => false,
+        };
+        if !is_compound {
+            return Err(self.unexpected("function")?);
+        }
+        let body = self.parse_command()?;
+        Ok(Command::FunctionDef(name, Rc::new(body)))
+    }
+
+    /// ( list ), the ( is already taken
+    fn parse_subshell(&mut self) -> Result<CompoundCommand, ParseError> {
+        let list = self.parse_nonempty_list("subsh")?;
+        match self.next()? {
+            Token::Op(x) if x == ")" => Ok(CompoundCommand::Subshell(list)),
+            Token::Eof => Err(ParseError::Incomplete(String::from("subsh"))),
+            x => Err(ParseError::Syntax(x.to_string())),
+        }
+    }
+
+    /// { list; }
+    fn parse_brace_group(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.next()?;
+        let list = self.parse_nonempty_list("brace")?;
+        self.expect_keyword("}", "brace")?;
+        Ok(CompoundCommand::BraceGroup(list))
+    }
+
+    fn parse_redirects(&mut self) -> Result<Vec<Redirect>, ParseError> {
+        let mut redirects = Vec::new();
+        while self.is_redirect_next()? {
+            redirects.push(self.parse_redirect()?);
+        }
+        Ok(redirects)
+    }
+
+    /// if list then list (elif list then list)* [else list] fi
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.next()?;
+        let mut branches = Vec::new();
+        let mut else_body = None;
+        loop {
+            let condition = self.parse_nonempty_list("if")?;
+            self.expect_keyword("then", "if")?;
+            let body = self.parse_nonempty_list("if")?;
+            branches.push((condition, body));
+            if self.peek_keyword("elif")? {
+                self.next()?;
+                continue;
+            }
+            if self.peek_keyword("else")? {
+                self.next()?;
+                else_body = Some(self.parse_nonempty_list("if")?);
+            }
+            break;
+        }
+        self.expect_keyword("fi", "if")?;
+        Ok(CompoundCommand::If(branches, else_body))
+    }
+
+    /// (while | until) list do list done
+    fn parse_while(&mut self) -> Result<CompoundCommand, ParseError> {
+        let is_while = self.peek_keyword("while")?;
+        let what = if is_while { "while" } else { "until" };
+        self.next()?;
+        let condition = self.parse_nonempty_list(what)?;
+        let body = self.parse_do_group(what)?;
+        if is_while {
+            Ok(CompoundCommand::While(condition, body))
+        } else {
+            Ok(CompoundCommand::Until(condition, body))
+        }
+    }
+
+    /// do list done
+    fn parse_do_group(&mut self, what: &str) -> Result<List, ParseError> {
+        self.expect_keyword("do", what)?;
+        let body = self.parse_nonempty_list(what)?;
+        self.expect_keyword("done", what)?;
+        Ok(body)
+    }
+
+    /// for name [in word*] (; | newline) do list done
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.next()?;
+        let name = match self.next()? {
+            Token::Word(x) if !x.is_quoted() && param::is_name(&x.raw()) => x.raw(),
+            Token::Eof => return Err(ParseError::Incomplete(String::from("for"))),
+            x => return Err(ParseError::Syntax(x.to_string())),
+        };
+        self.skip_newlines()?;
+        let mut words = None;
+        if self.peek_keyword("in")? {
+            self.next()?;
+            let mut list = Vec::new();
+            while let Token::Word(_) = self.peek()? {
+                if let Token::Word(x) = self.next()? {
+                    list.push(x);
+                }
+            }
+            words = Some(list);
+            if !self.peek_op(";")? && !self.peek_op("\n")? {
+                return Err(self.unexpected("for")?);
+            }
+            self.next()?;
+        } else if self.peek_op(";")? {
+            self.next()?;
+        }
+        self.skip_newlines()?;
+        let body = self.parse_do_group("for")?;
+        Ok(CompoundCommand::For(name, words, body))
+    }
+
+    /// case word in ([(] pattern (| pattern)* ) list ;;)* esac
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.next()?;
+        let word = match self.next()? {
+            Token::Word(x) => x,
+            Token::Eof => return Err(ParseError::Incomplete(String::from("case"))),
+            x => return Err(ParseError::Syntax(x.to_string())),
+        };
+        self.skip_newlines()?;
+        self.expect_keyword("in", "case")?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines()?;
+            if self.peek_keyword("esac")? {
+                break;
+            }
+            if self.peek_op("(")? {
+                self.next()?;
+            }
+            let mut patterns: Vec<Word> = Vec::new();
+            loop {
+                match self.next()? {
+                    Token::Word(x) => patterns.push(x),
+                    Token::Eof => return Err(ParseError::Incomplete(String::from("case"))),
+                    x => return Err(ParseError::Syntax(x.to_string())),
+                };
+                if !self.peek_op("|")? {
+                    break;
+                }
+                self.next()?;
+            }
+            if !self.peek_op(")")? {
+                return Err(self.unexpected("case")?);
+            }
+            self.next()?;
+            let body = self.parse_list()?;
+            items.push(CaseItem { patterns, body });
+            if !self.peek_op(";;")? {
+                break;
+            }
+            self.next()?;
+        }
+        self.expect_keyword("esac", "case")?;
+        Ok(CompoundCommand::Case(word, items))
+    }
+
+    /// the error for an unexpected next token, incomplete at the end of input
+    fn unexpected(&mut self, what: &str) -> Result<ParseError, ParseError> {
+        Ok(match self.peek()? {
+            Token::Eof => ParseError::Incomplete(what.to_string()),
+            x => ParseError::Syntax(x.to_string()),
+        })
+    }
+
+    /// simple_command: (word | redirect)+
+    fn parse_simple_command(&mut self) -> Result<Command, ParseError> {
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+        loop {
+            if self.is_redirect_next()? {
+                redirects.push(self.parse_redirect()?);
+            } else if let Token::Word(_) = self.peek()? {
+                if let Token::Word(x) = self.next()? {
+                    words.push(x);
+                }
+            } else {
+                break;
+            }
+        }
+        if words.is_empty() && redirects.is_empty() {
+            return Err(ParseError::Syntax(self.peek()?.to_string()));
+        }
+        Ok(Command::Simple(SimpleCommand { words, redirects }))
+    }
+
+    fn is_redirect_next(&mut self) -> Result<bool, ParseError> {
+        Ok(match self.peek()? {
+            Token::IoNumber(_) | Token::IoVar(_) => true,
+            Token::Op(x) => REDIRECT_OPERATORS.contains(&x.as_str()),
+            _ => false,
+        })
+    }
+
+    /// redirect: [n | {name}] op word, heredoc bodies are read right away
+    fn parse_redirect(&mut self) -> Result<Redirect, ParseError> {
+        let (fd, fd_var) = match self.peek()? {
+            Token::IoNumber(x) => (Some(*x), None),
+            Token::IoVar(x) => (None, Some(x.clone())),
+            _ => (None, None),
+        };
+        if fd.is_some() || fd_var.is_some() {
+            self.next()?;
+        }
+        let op = match self.next()? {
+            Token::Op(x) if REDIRECT_OPERATORS.contains(&x.as_str()) => x,
+            x => return Err(ParseError::Syntax(x.to_string())),
+        };
+        let word = match self.next()? {
+            Token::Word(x) => x,
+            x => return Err(ParseError::Syntax(x.to_string())),
+        };
+        let mut here_doc = String::new();
+        if op == "<<" || op == "<<-" {
+            here_doc = self.lexer.read_here_doc(&word.unquoted(), op == "<<-")?;
+        }
+        Ok(Redirect { fd, fd_var, op, word, here_doc })
+    }
+}
+
+/// function names are a bit looser than variable names, eg: my-func
+fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|x| x.is_alphanumeric() || "_-.:".contains(x))
+        && !COMPOUND_KEYWORDS.contains(&name)
+        && !LIST_TERMINATORS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the first simple command of a parsed list
+    fn first_simple(list: &List) -> &SimpleCommand {
+        match &list.items[0].and_or.first.cmds[0] {
+            Command::Simple(x) => x,
+            x => panic!("not a simple command: {:?}", x),
+        }
+    }
+
+    /// the error parsing text gives when more input may follow
+    fn error(text: &str) -> Option<ParseError> {
+        parse(text, false, true).err()
+    }
+
+    fn incomplete(what: &str) -> Option<ParseError> {
+        Some(ParseError::Incomplete(what.to_string()))
+    }
+
+    #[test]
+    fn lists() {
+        let list = parse("a | b && c; d &", true, true).unwrap();
+        assert_eq!(list.items.len(), 2);
+        assert_eq!(list.items[0].and_or.text(), "a | b && c");
+        assert!(!list.items[0].is_background);
+        assert!(list.items[1].is_background);
+        let words: Vec<String> = first_simple(&list).words.iter().map(|x| x.raw()).collect();
+        assert_eq!(words, ["a"]);
+    }
+
+    #[test]
+    fn quoting() {
+        let list = parse("echo 'a b' \"c d\" e\\ f", true, true).unwrap();
+        let words: Vec<String> = first_simple(&list).words.iter().map(|x| x.unquoted()).collect();
+        assert_eq!(words, ["echo", "a b", "c d", "e f"]);
+        // quoted reserved words are plain words
+        assert!(parse("'if' a", true, true).is_ok());
+    }
+
+    #[test]
+    fn here_docs() {
+        let list = parse("cat <<A; cat <<-'B'\none $x\nA\n\ttwo\n\tB\necho done\n", true, true).unwrap();
+        assert_eq!(list.items.len(), 3);
+        assert_eq!(first_simple(&list).redirects[0].here_doc, "one $x\n");
+        match &list.items[1].and_or.first.cmds[0] {
+            Command::Simple(x) => {
+                assert_eq!(x.redirects[0].op, "<<-");
+                assert_eq!(x.redirects[0].here_doc, "two\n");
+            },
+            x => panic!("not a simple command: {:?}", x),
+        };
+        // the body may end at the end of input, but only once no more input follows
+        assert_eq!(error("cat <<A\nbody\n"), incomplete("heredoc"));
+        assert!(parse("cat <<A\nbody\n", true, true).is_ok());
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert_eq!(error("a &&"), incomplete("cmdand"));
+        assert_eq!(error("a ||\n"), incomplete("cmdor"));
+        assert_eq!(error("a |"), incomplete("pipe"));
+        assert_eq!(error("if a; then b"), incomplete("if"));
+        assert_eq!(error("while a"), incomplete("while"));
+        assert_eq!(error("for i in a b; do"), incomplete("for"));
+        assert_eq!(error("case a in"), incomplete("case"));
+        assert_eq!(error("f() {"), incomplete("brace"));
+        assert_eq!(error("(a"), incomplete("subsh"));
+        assert_eq!(error("echo \"a"), incomplete("dquote"));
+        assert_eq!(error("echo $((1 +"), incomplete("mathsubst"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(parse("a; fi", true, true).err(), Some(ParseError::Syntax(String::from("fi"))));
+        assert_eq!(parse("a )", true, true).err(), Some(ParseError::Syntax(String::from(")"))));
+        assert_eq!(parse("| a", true, true).err(), Some(ParseError::Syntax(String::from("|"))));
+    }
+
+    #[test]
+    fn lines_read_as_needed() {
+        let mut lines = ["f() {", "  a", "}; f", "b", "if"].iter().map(|x| x.to_string());
+        let mut parser = script_parser(&mut lines);
+        let list = parser.parse_line().unwrap().unwrap();
+        assert_eq!(list.items.len(), 2);
+        assert!(matches!(list.items[0].and_or.first.cmds[0], Command::FunctionDef(..)));
+        // the lines after the command are left for whoever reads them next
+        assert_eq!(parser.lexer.slice(0, usize::MAX), "f() {\n  a\n}; f\n");
+        assert_eq!(first_simple(&parser.parse_line().unwrap().unwrap()).words[0].raw(), "b");
+        assert_eq!(parser.parse_line().err(), Some(ParseError::Incomplete(String::from("if"))));
+        assert!(parser.parse_line().unwrap().is_none());
+    }
+}
@@ -1,37 +1,48 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use crate::parser::parse_line;
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+use termios::Termios;
+
+use crate::parser::ast::{Command, CompoundCommand, Pipeline};
 
 pub type Token = (String, String);
 pub type Tokens = Vec<Token>;
 
-#[derive(Debug, Clone)]
-pub struct RedirTo {
-    pub redir_type: String,
-    pub fd_before: i32,
-    pub fd_after: i32,
-    pub file_after: String
-}
-
-#[derive(Debug, Clone)]
-pub struct RedirFrom {
-    pub redir_type: String,
-    pub fd_after: i32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirType {
+    Input,          // < file
+    Output,         // > file, an existing file is kept with noclobber
+    Clobber,        // >| file, noclobber or not
+    Append,         // >> file
+    ReadWrite,      // <> file
+    OutputAll,      // &> file, both stdout and stderr
+    AppendAll,      // &>> file
+    DupOutput,      // n>&m, n>&- closes n, >&file is &>file
+    DupInput,       // n<&m, n<&- closes n
+    HereDoc,        // << and <<-
+    HereString      // <<< word
 }
 
+/// a redirection of a command, they are applied from left to right
+/// eg: "2>&1" => { redir_type: DupOutput, fd: 2, word: "1" }
 #[derive(Debug, Clone)]
-pub struct LineInfo {
-    pub tokens: Tokens,
-    pub is_complete: bool,
-    pub here_doc: String,
-    pub unmatched: String
+pub struct Redir {
+    pub redir_type: RedirType,
+    pub fd: i32,                    // fd being redirected, given or the default of the type
+    pub fd_var: Option<String>,     // {name}>file, a free fd is used instead and stored in name
+    pub has_fd: bool,               // whether the fd was given, >&file is only &>file without it
+    pub word: Token,                // file, fd, delimiter for heredocs, string for here-strings
+    pub here_doc: String            // body of the heredoc
 }
 
 #[derive(Debug, Clone)]
 pub struct CmdInfo {
     pub tokens: Tokens,
-    pub redir_from: Option<RedirFrom>,
-    pub redir_to: Option<Vec<RedirTo>>
+    pub assignments: Tokens,                // NAME=value before the command name
+    pub redirects: Vec<Redir>,
+    pub compound: Option<CompoundCommand>   // run instead of tokens for compound commands
 }
 
 #[derive(Debug, Clone)]
@@ -42,46 +53,73 @@ pub struct CmdlineInfo {
 }
 
 impl CmdInfo {
-    pub fn from(tokens: Tokens) -> Result<CmdInfo, String> {
-        let cmd_info;
-        match parse_line::tokens_check_redir_to(&tokens) {
-            Ok(x) => cmd_info = x,
-            Err(e) => return Err(e)
+    pub fn from(cmd: &Command) -> CmdInfo {
+        let (words, redirects, compound) = match cmd {
+            Command::Simple(x) => (&x.words[..], &x.redirects[..], None),
+            Command::Compound(x, y) => (&[][..], &y[..], Some(x.clone())),
+            // definitions are only run alone, in a pipeline they do nothing
+            Command::FunctionDef(..) => (&[][..], &[][..], None),
         };
+        let assignment_num = words.iter().take_while(|x| x.is_assignment()).count();
+        let assignments = words[..assignment_num].iter().map(|x| x.to_token()).collect();
+        let tokens = words[assignment_num..].iter().map(|x| x.to_token()).collect();
+        let redirects = redirects.iter().map(|x| {
+            let (redir_type, fd_default) = match x.op.as_str() {
+                "<" => (RedirType::Input, 0),
+                ">" => (RedirType::Output, 1),
+                ">|" => (RedirType::Clobber, 1),
+                ">>" => (RedirType::Append, 1),
+                "<>" => (RedirType::ReadWrite, 0),
+                "&>" => (RedirType::OutputAll, 1),
+                "&>>" => (RedirType::AppendAll, 1),
+                ">&" => (RedirType::DupOutput, 1),
+                "<&" => (RedirType::DupInput, 0),
+                "<<<" => (RedirType::HereString, 0),
+                _ => (RedirType::HereDoc, 0),
+            };
+            // a heredoc with any quote in its delimiter is not expanded
+            let word = if redir_type == RedirType::HereDoc {
+                let quote = if x.word.is_quoted() { "\'" } else { "" };
+                (String::from(quote), x.word.unquoted())
+            } else {
+                x.word.to_token()
+            };
+            Redir {
+                redir_type,
+                fd: x.fd.unwrap_or(fd_default),
+                fd_var: x.fd_var.clone(),
+                has_fd: x.fd.is_some() || x.fd_var.is_some(),
+                word,
+                here_doc: x.here_doc.clone()
+            }
+        }).collect();
+        CmdInfo { tokens, assignments, redirects, compound }
+    }
 
-        Ok(cmd_info)
+    /// what its process runs, for job listings, eg: "sleep 10", "( ... )"
+    pub fn argv(&self) -> String {
+        let compound = match &self.compound {
+            Some(x) => x,
+            None => return self.tokens.iter().map(|x| x.1.as_str()).collect::<Vec<_>>().join(" "),
+        };
+        match compound {
+            CompoundCommand::Arith(x) => format!("(({}))", x),
+            CompoundCommand::BraceGroup(_) => String::from("{ ... }"),
+            CompoundCommand::Subshell(_) => String::from("( ... )"),
+            CompoundCommand::If(..) => String::from("if ..."),
+            CompoundCommand::While(..) => String::from("while ..."),
+            CompoundCommand::Until(..) => String::from("until ..."),
+            CompoundCommand::For(..) => String::from("for ..."),
+            CompoundCommand::Case(..) => String::from("case ..."),
+        }
     }
 }
 
 impl CmdlineInfo {
-    pub fn from(line: &str) -> Result<CmdlineInfo, String> {
-        let mut is_background = false;
-        let mut cmds = Vec::new();
-        let mut lineinfo = parse_line::line_to_tokens(line);
-        // TODO: expand $(), ${}, ``...
-        // let mut cmds = Vector::new();
-        // check whether is background
-        match lineinfo.tokens.iter().last() {
-            Some(x) => {
-                if x.1 == "&" {
-                    is_background = true;
-                }
-            },
-            None => {}
-        };
-        if is_background {
-            lineinfo.tokens.pop();
-        }
-        // split tokens into vector of subtokens, seperated by pipes
-        let sub_tokens: Vec<Tokens> = parse_line::break_line_by_pipe(&lineinfo.tokens);
-        for sub_token in sub_tokens {
-            let cmd_info = match CmdInfo::from(sub_token) {
-                Ok(x) => x,
-                Err(e) => return Err(e)
-            };
-            cmds.push(cmd_info);
-        }
-        Ok(CmdlineInfo { line: String::from(line), cmds: cmds, is_background: is_background })
+    pub fn from(pipeline: &Pipeline, is_background: bool) -> CmdlineInfo {
+        // expansions need the shell, they are done by the executor right before running
+        let cmds = pipeline.cmds.iter().map(CmdInfo::from).collect();
+        CmdlineInfo { line: pipeline.text.clone(), cmds, is_background }
     }
 }
 
@@ -91,54 +129,202 @@ pub struct Job {
     pub id: i32,                        // job id
     pub pgid: i32,                      // process group id
     pub pids: Vec<i32>,                 // pids that belong to this process group, that are still running
-    pub status: HashMap<i32, JobStatus> // key: pid, value: job status
+    pub all_pids: Vec<i32>,             // all of its pids in pipeline order, the done ones too
+    pub status: HashMap<i32, JobStatus>,// key: pid, value: job status
+    pub argvs: HashMap<i32, String>,    // key: pid, value: what it runs
+    pub cpu_times: HashMap<i32, (Duration, Duration)>,  // key: pid, value: user and system time, once done
+    pub start: Instant,                 // when it was started
+    pub helpers: Vec<i32>,              // pids of process substitutions, reaped with the job
+    pub line: String,                   // the command line the job runs
+    pub tty_modes: Option<Termios>,     // the terminal modes it had when it was stopped in foreground
+    pub changed: bool                   // it got stopped or continued in background, not reported yet
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum JobStatus {
     Running,
     Exited(i32),
+    Signaled(Signal, bool),     // the signal, whether it dumped core
     Stopped,
-    None
+    Continued                   // running again, not reported yet
+}
+
+impl JobStatus {
+    pub fn from(status: &WaitStatus) -> JobStatus {
+        match status {
+            WaitStatus::Exited(_, x) => JobStatus::Exited(*x),
+            WaitStatus::Signaled(_, signal, core_dumped) => JobStatus::Signaled(*signal, *core_dumped),
+            WaitStatus::Stopped(..) => JobStatus::Stopped,
+            WaitStatus::Continued(_) => JobStatus::Continued,
+            _ => JobStatus::Running,
+        }
+    }
+
+    /// the exit status it stands for, 128 + the signal for signaled processes
+    pub fn code(&self) -> i32 {
+        match self {
+            JobStatus::Exited(x) => *x,
+            JobStatus::Signaled(signal, _) => 128 + *signal as i32,
+            _ => 0,
+        }
+    }
+
+    /// whether the process is gone, it exited or was killed by a signal
+    pub fn is_final(&self) -> bool {
+        matches!(self, JobStatus::Exited(_) | JobStatus::Signaled(..))
+    }
+
+    /// as job listings put it, eg: "done", "exit 1", "segmentation fault (core dumped)"
+    pub fn describe(&self) -> String {
+        match self {
+            JobStatus::Running => String::from("running"),
+            JobStatus::Exited(0) => String::from("done"),
+            JobStatus::Exited(x) => format!("exit {}", x),
+            JobStatus::Signaled(signal, core_dumped) => {
+                let name = match signal {
+                    Signal::SIGHUP => String::from("hangup"),
+                    Signal::SIGINT => String::from("interrupt"),
+                    Signal::SIGQUIT => String::from("quit"),
+                    Signal::SIGILL => String::from("illegal hardware instruction"),
+                    Signal::SIGABRT => String::from("abort"),
+                    Signal::SIGBUS => String::from("bus error"),
+                    Signal::SIGFPE => String::from("floating point exception"),
+                    Signal::SIGKILL => String::from("killed"),
+                    Signal::SIGSEGV => String::from("segmentation fault"),
+                    Signal::SIGPIPE => String::from("broken pipe"),
+                    Signal::SIGALRM => String::from("alarm"),
+                    Signal::SIGTERM => String::from("terminated"),
+                    Signal::SIGUSR1 => String::from("user-defined signal 1"),
+                    Signal::SIGUSR2 => String::from("user-defined signal 2"),
+                    x => format!("signal {}", *x as i32),
+                };
+                if *core_dumped { format!("{} (core dumped)", name) } else { name }
+            },
+            JobStatus::Stopped => String::from("suspended"),
+            JobStatus::Continued => String::from("continued"),
+        }
+    }
 }
 
 impl Job {
-    pub fn print_status(self: &Self) {
-        if self.status.is_empty() {
+    /// a job is stopped if any of its processes left is
+    pub fn is_stopped(&self) -> bool {
+        self.pids.iter().any(|x| self.status.get(x) == Some(&JobStatus::Stopped))
+    }
+
+    /// a job is done once its processes and process substitutions are
+    pub fn is_done(&self) -> bool {
+        self.pids.is_empty() && self.helpers.is_empty()
+    }
+
+    /// the state of the job as a whole, the status of its last process once done
+    pub fn state(&self) -> String {
+        if self.is_stopped() {
+            return JobStatus::Stopped.describe();
+        }
+        if self.is_done() {
+            return self.all_pids.last().map_or(String::from("done"), |x| self.process_state(*x));
+        }
+        if self.pids.iter().any(|x| self.status.get(x) == Some(&JobStatus::Continued)) {
+            return JobStatus::Continued.describe();
+        }
+        JobStatus::Running.describe()
+    }
+
+    /// set the status of the processes left, eg: all running once continued
+    pub fn set_status(&mut self, status: JobStatus) {
+        for pid in self.pids.iter() {
+            self.status.insert(*pid, status);
+        }
+    }
+
+    /// once its stop or continuation is reported, continued processes are just running
+    pub fn set_reported(&mut self) {
+        self.changed = false;
+        for pid in self.pids.iter() {
+            if self.status.get(pid) == Some(&JobStatus::Continued) {
+                self.status.insert(*pid, JobStatus::Running);
+            }
+        }
+    }
+
+    /// the notice of a job done, with the status of each process if they differ
+    /// eg: "[1]  + terminated  yes | head", then "       4242 terminated  yes", "       4243 done       head"
+    pub fn print_status(&self, mark: char) {
+        println!("{:<5}{} {:<10} {}", format!("[{}]", self.id), mark, self.state(), self.line);
+        let statuses: Vec<JobStatus> = self.all_pids.iter().filter_map(|x| self.status.get(x)).copied().collect();
+        if statuses.iter().all(|x| *x == statuses[0]) {
             return;
         }
-        let mut job_status_prev = JobStatus::None;
-        let mut string = String::new();
-        for (idx, (pid, job_status)) in self.status.iter().enumerate() {
-            if *job_status == job_status_prev {
-                string.push_str(format!("  {}", pid).as_str());
-            } else {
-                if !string.is_empty() {
-                    println!("{}", string);
-                    string = String::new();
-                }
-                if idx == 0 {
-                    string.push_str(format!("{: <6}", format!("[{}]", self.id)).as_str());
-                } else {
-                    string.push_str("      ");
-                }
-                match job_status {
-                    JobStatus::Running => string.push_str(format!("running <15").as_str()),
-                    JobStatus::Exited(x) => {
-                        if *x == 0 {
-                            string.push_str(format!("{: <15}", "done").as_str());
-                        } else {
-                            string.push_str(format!("{: <15}", format!("exit {}", x)).as_str());
-                        }
-                    },
-                    JobStatus::Stopped => string.push_str(format!("suspended <15").as_str()),
-                    JobStatus::None => {},  // impossible...
-                }
-                job_status_prev = *job_status;
+        for line in self.process_lines(false) {
+            println!("{}", line);
+        }
+    }
+
+    /// the state of one of its processes, one that is gone without a status it was reaped with is just done
+    fn process_state(&self, pid: i32) -> String {
+        match self.status.get(&pid).copied().unwrap_or(JobStatus::Running) {
+            x if !x.is_final() && !self.pids.contains(&pid) => String::from("done"),
+            x => x.describe(),
+        }
+    }
+
+    /// a line for each of its processes, with_times adds the cpu times of the done ones
+    /// eg: "       4243 exit 1     grep foo  0.01s user 0.00s system"
+    pub fn process_lines(&self, with_times: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        for pid in self.all_pids.iter() {
+            let argv = self.argvs.get(pid).map(|x| x.as_str()).unwrap_or_default();
+            let mut line = format!("       {} {:<10} {}", pid, self.process_state(*pid), argv);
+            if let Some((user, system)) = self.cpu_times.get(pid).filter(|_| with_times) {
+                line.push_str(&format!("  {:.2}s user {:.2}s system", user.as_secs_f64(), system.as_secs_f64()));
             }
+            lines.push(line);
         }
-        if !string.is_empty() {
-            println!("{}", string);
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(pids: &[i32], status: &[(i32, JobStatus)]) -> Job {
+        Job {
+            id: 1,
+            pgid: pids[0],
+            pids: Vec::new(),
+            all_pids: pids.to_vec(),
+            status: status.iter().copied().collect(),
+            argvs: HashMap::new(),
+            cpu_times: HashMap::new(),
+            start: Instant::now(),
+            helpers: Vec::new(),
+            line: String::from("a | b"),
+            tty_modes: None,
+            changed: false,
         }
     }
+
+    #[test]
+    fn done_state() {
+        assert_eq!(job(&[10, 11], &[(10, JobStatus::Exited(0)), (11, JobStatus::Exited(2))]).state(), "exit 2");
+        assert_eq!(job(&[10], &[(10, JobStatus::Signaled(Signal::SIGKILL, false))]).state(), "killed");
+        // processes gone without a status they were reaped with are done, never running
+        let never_reaped = job(&[10, 11], &[(10, JobStatus::Exited(1)), (11, JobStatus::Running)]);
+        assert!(never_reaped.is_done());
+        assert_eq!(never_reaped.state(), "done");
+        assert_eq!(job(&[10], &[(10, JobStatus::Continued)]).state(), "done");
+        assert!(never_reaped.process_lines(false)[1].contains(" done "));
+        assert!(never_reaped.process_lines(false)[0].contains(" exit 1 "));
+    }
+
+    #[test]
+    fn running_state() {
+        let mut running = job(&[10, 11], &[(10, JobStatus::Exited(0)), (11, JobStatus::Running)]);
+        running.pids.push(11);
+        assert_eq!(running.state(), "running");
+        running.status.insert(11, JobStatus::Stopped);
+        assert_eq!(running.state(), "suspended");
+    }
 }